@@ -1,10 +1,17 @@
-use crate::{error::Error, login::Secrets};
+use crate::{
+	error::Error,
+	login::{get_renewed_session_secrets, Secrets},
+};
+use indexmap::IndexMap;
+use reqwest::{header::HeaderMap, ResponseBuilderExt};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use serde_repr::Deserialize_repr;
-use std::collections::HashMap;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::{trace, warn};
 const USER_AGENT: &str = "fumosync-rs (github.com/techs-sus/fumosync)";
-const BASE_URL: &str = "https://fumosclubv1.vercel.app";
+pub const BASE_URL: &str = "https://fumosclubv1.vercel.app";
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -64,7 +71,7 @@ pub struct Editor {
 	pub script_info: EditorScriptInfo,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum EditorUpdate<'a> {
 	Description(&'a str),
 	Module { name: &'a str, source: &'a str },
@@ -75,37 +82,271 @@ pub enum EditorUpdate<'a> {
 	Publicity(bool),
 }
 
+impl EditorUpdate<'_> {
+	/// Short human readable name of what this update changes.
+	pub fn label(&self) -> String {
+		match self {
+			EditorUpdate::Description(_) => "description".to_owned(),
+			EditorUpdate::Module { name, .. } => format!("module {name}"),
+			EditorUpdate::MainSource(_) => "main source".to_owned(),
+			EditorUpdate::Whitelist(_) => "whitelist".to_owned(),
+			EditorUpdate::Name(_) => "name".to_owned(),
+			EditorUpdate::Publicity(_) => "publicity".to_owned(),
+		}
+	}
+
+	/// The source text this update carries, if it carries one.
+	pub fn text(&self) -> Option<&str> {
+		match self {
+			EditorUpdate::Description(value) | EditorUpdate::MainSource(value) => Some(value),
+			EditorUpdate::Module { source, .. } => Some(source),
+			EditorUpdate::Whitelist(_) | EditorUpdate::Name(_) | EditorUpdate::Publicity(_) => None,
+		}
+	}
+
+	/// Whether the remote script already holds what this update would write.
+	pub fn is_applied_to(&self, info: &EditorScriptInfo) -> bool {
+		match self {
+			EditorUpdate::Description(value) => info.description == *value,
+			EditorUpdate::Module { name, source } => info
+				.source
+				.modules
+				.get(*name)
+				.is_some_and(|remote| remote == source),
+			EditorUpdate::MainSource(source) => info.source.main == *source,
+			EditorUpdate::Whitelist(whitelist) => info
+				.whitelist
+				.iter()
+				.map(String::as_str)
+				.eq(whitelist.iter().copied()),
+			EditorUpdate::Name(name) => info.name == *name,
+			EditorUpdate::Publicity(public) => info.is_public == *public,
+		}
+	}
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct Source {
 	pub main: String,
-	// key -> source
-	pub modules: HashMap<String, String>,
+	// key -> source, in the order the server lists them
+	pub modules: IndexMap<String, String>,
+}
+
+/// Network behaviour of a `Client`.
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+	/// Root of the fumosclub API, without a trailing slash.
+	pub base_url: String,
+	/// How long a single request may take before it's abandoned.
+	pub timeout: Duration,
+	/// How many times a request is retried after a timeout, connection failure or 5xx status.
+	pub retries: u32,
+	/// How long to wait before each retry.
+	pub backoff: Backoff,
+	/// Log every request and response in full at trace level, with the session redacted.
+	pub trace_http: bool,
+}
+
+impl Default for ClientOptions {
+	fn default() -> Self {
+		Self {
+			base_url: BASE_URL.to_owned(),
+			timeout: Duration::from_secs(30),
+			retries: 0,
+			backoff: Backoff::default(),
+			trace_http: false,
+		}
+	}
+}
+
+/// Exponential backoff between retries: `base_delay`, then `multiplier` times longer each retry,
+/// never longer than `max_delay`.
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+	base_delay: Duration,
+	multiplier: f64,
+	max_delay: Duration,
+}
+
+impl Backoff {
+	pub const DEFAULT_BASE_DELAY: Duration = Duration::from_millis(500);
+	pub const DEFAULT_MULTIPLIER: f64 = 2.0;
+	pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(32);
+
+	/// Clamps the parameters to sane ranges: a base delay of 10ms to 1 minute, a multiplier of 1 to
+	/// 10, and a max delay of at least the base delay and at most 10 minutes.
+	pub fn new(base_delay: Duration, multiplier: f64, max_delay: Duration) -> Self {
+		let base_delay = base_delay.clamp(Duration::from_millis(10), Duration::from_secs(60));
+		Self {
+			base_delay,
+			multiplier: match multiplier.is_nan() {
+				true => 2.0,
+				false => multiplier.clamp(1.0, 10.0),
+			},
+			max_delay: max_delay.clamp(base_delay, Duration::from_secs(600)),
+		}
+	}
+
+	/// How long to wait before the `attempt`th retry, counting from 1.
+	pub fn delay(&self, attempt: u32) -> Duration {
+		let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+		self
+			.base_delay
+			.mul_f64(factor.min(self.max_delay.div_duration_f64(self.base_delay)))
+	}
 }
 
+impl Default for Backoff {
+	fn default() -> Self {
+		Self::new(
+			Self::DEFAULT_BASE_DELAY,
+			Self::DEFAULT_MULTIPLIER,
+			Self::DEFAULT_MAX_DELAY,
+		)
+	}
+}
+
+#[derive(Clone)]
 pub struct Client {
 	pub secrets: Secrets,
 	client: reqwest::Client,
+	options: ClientOptions,
 }
 
 impl Client {
-	pub fn new(secrets: Secrets) -> Self {
+	pub fn new(secrets: Secrets, options: ClientOptions) -> Self {
 		Self {
 			secrets,
-			client: reqwest::Client::new(),
+			client: reqwest::Client::builder()
+				.timeout(options.timeout)
+				.build()
+				.expect("failed building http client"),
+			options,
 		}
 	}
 
+	/// Builds a client from the session saved by `fumosync login`, renewing it if it's about to
+	/// expire.
+	pub async fn from_saved_session(options: ClientOptions) -> Result<Self, Error> {
+		Ok(Self::new(
+			get_renewed_session_secrets(&options).await?,
+			options,
+		))
+	}
+
+	/// Sends a request, retrying it with exponential backoff when the failure looks transient.
+	async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response, Error> {
+		let mut attempt = 0;
+		loop {
+			let attempt_request = request.try_clone().expect("request bodies to be buffered");
+			let result = match self.options.trace_http {
+				true => self.send_traced(attempt_request).await,
+				false => attempt_request.send().await,
+			};
+
+			let transient = match &result {
+				Ok(response) => response.status().is_server_error(),
+				Err(e) => e.is_timeout() || e.is_connect(),
+			};
+			if !transient || attempt >= self.options.retries {
+				return Ok(result?);
+			}
+
+			attempt += 1;
+			warn!(
+				target: "network",
+				"request failed, retrying ({attempt}/{})",
+				self.options.retries
+			);
+			tokio::time::sleep(self.options.backoff.delay(attempt)).await;
+		}
+	}
+
+	/// Sends `request` like `RequestBuilder::send` does, logging it and its response at trace
+	/// level. The response body has to be read for that, so an equivalent response is returned.
+	async fn send_traced(
+		&self,
+		request: reqwest::RequestBuilder,
+	) -> Result<reqwest::Response, reqwest::Error> {
+		let (client, request) = request.build_split();
+		let request = request?;
+		let body = request
+			.body()
+			.and_then(reqwest::Body::as_bytes)
+			.map(String::from_utf8_lossy)
+			.unwrap_or_default();
+		trace!(
+			target: "http",
+			"{} {}\n{}{}",
+			request.method(),
+			self.redact(request.url().as_str()),
+			self.redact_headers(request.headers()),
+			self.redact(&body)
+		);
+
+		let url = request.url().clone();
+		let response = client.execute(request).await?;
+		let status = response.status();
+		let version = response.version();
+		let headers = response.headers().clone();
+		let body = response.bytes().await?;
+		trace!(
+			target: "http",
+			"{status} from {}\n{}{}",
+			self.redact(url.as_str()),
+			self.redact_headers(&headers),
+			self.redact(&String::from_utf8_lossy(&body))
+		);
+
+		let mut rebuilt = http::Response::builder()
+			.status(status)
+			.version(version)
+			.url(url);
+		if let Some(rebuilt_headers) = rebuilt.headers_mut() {
+			*rebuilt_headers = headers;
+		}
+		Ok(
+			rebuilt
+				.body(body)
+				.expect("the parts of a received response to be valid")
+				.into(),
+		)
+	}
+
+	/// `text` with the session replaced by `<redacted>`.
+	fn redact(&self, text: &str) -> String {
+		match self.secrets.session.is_empty() {
+			true => text.to_owned(),
+			false => text.replace(&self.secrets.session, "<redacted>"),
+		}
+	}
+
+	/// One `name: value` line per header, with cookies and credentials redacted.
+	fn redact_headers(&self, headers: &HeaderMap) -> String {
+		let mut lines = String::new();
+		for (name, value) in headers {
+			let value = match name.as_str() {
+				"cookie" | "set-cookie" | "authorization" | "proxy-authorization" => "<redacted>".into(),
+				_ => self.redact(&String::from_utf8_lossy(value.as_bytes())),
+			};
+			lines.push_str(&format!("{name}: {value}\n"));
+		}
+		lines
+	}
+
 	pub async fn get_details(&self) -> Result<AccountDetails, Error> {
 		Ok(serde_json::from_slice(
 			&self
-				.client
-				.get(format!("{BASE_URL}/api/account/getdetails"))
-				.header(
-					"Cookie",
-					format!("session={}", self.secrets.session.clone()),
+				.send(
+					self
+						.client
+						.get(format!("{}/api/account/getdetails", self.options.base_url))
+						.header(
+							"Cookie",
+							format!("session={}", self.secrets.session.clone()),
+						)
+						.header("User-Agent", USER_AGENT),
 				)
-				.header("User-Agent", USER_AGENT)
-				.send()
 				.await?
 				.bytes()
 				.await?,
@@ -121,18 +362,20 @@ impl Client {
 
 		let value: Key = serde_json::from_slice(
 			&self
-				.client
-				.post(format!("{BASE_URL}/api/script/generatekey"))
-				.header(
-					"Cookie",
-					format!("session={}", self.secrets.session.clone()),
+				.send(
+					self
+						.client
+						.post(format!("{}/api/script/generatekey", self.options.base_url))
+						.header(
+							"Cookie",
+							format!("session={}", self.secrets.session.clone()),
+						)
+						.header("User-Agent", USER_AGENT)
+						.header("Content-Type", "application/json")
+						.body(serde_json::to_string(&json!({
+							"scriptId": id
+						}))?),
 				)
-				.header("User-Agent", USER_AGENT)
-				.header("Content-Type", "application/json")
-				.body(serde_json::to_string(&json!({
-					"scriptId": id
-				}))?)
-				.send()
 				.await?
 				.bytes()
 				.await?,
@@ -145,14 +388,19 @@ impl Client {
 	pub async fn list_scripts(&self) -> Result<ScriptList, Error> {
 		Ok(serde_json::from_slice(
 			&self
-				.client
-				.get(format!("{BASE_URL}/api/script/home/getscripts"))
-				.header(
-					"Cookie",
-					format!("session={}", self.secrets.session.clone()),
+				.send(
+					self
+						.client
+						.get(format!(
+							"{}/api/script/home/getscripts",
+							self.options.base_url
+						))
+						.header(
+							"Cookie",
+							format!("session={}", self.secrets.session.clone()),
+						)
+						.header("User-Agent", USER_AGENT),
 				)
-				.header("User-Agent", USER_AGENT)
-				.send()
 				.await?
 				.bytes()
 				.await?,
@@ -161,21 +409,28 @@ impl Client {
 
 	/// Gets the editor for an id.
 	pub async fn get_editor(&self, id: &str) -> Result<Editor, Error> {
-		Ok(serde_json::from_slice(
-			&self
-				.client
-				.get(format!("{BASE_URL}/api/script/editor"))
-				.query(&[("id", id)])
-				.header(
-					"Cookie",
-					format!("session={}", self.secrets.session.clone()),
+		Ok(serde_json::from_str(&self.get_editor_raw(id).await?)?)
+	}
+
+	/// Gets the editor for an id as the server sent it, without deserializing it.
+	pub async fn get_editor_raw(&self, id: &str) -> Result<String, Error> {
+		Ok(
+			self
+				.send(
+					self
+						.client
+						.get(format!("{}/api/script/editor", self.options.base_url))
+						.query(&[("id", id)])
+						.header(
+							"Cookie",
+							format!("session={}", self.secrets.session.clone()),
+						)
+						.header("User-Agent", USER_AGENT),
 				)
-				.header("User-Agent", USER_AGENT)
-				.send()
 				.await?
-				.bytes()
+				.text()
 				.await?,
-		)?)
+		)
 	}
 
 	pub async fn set_editor(&self, id: &str, updates: &[EditorUpdate<'_>]) -> Result<(), Error> {
@@ -198,7 +453,7 @@ impl Client {
 		#[serde(rename_all = "camelCase")]
 		struct Source<'a> {
 			#[serde(skip_serializing_if = "Option::is_none")]
-			pub modules: Option<HashMap<&'a str, &'a str>>,
+			pub modules: Option<IndexMap<&'a str, &'a str>>,
 			#[serde(skip_serializing_if = "Option::is_none")]
 			pub main: Option<&'a str>,
 		}
@@ -229,7 +484,7 @@ impl Client {
 				EditorUpdate::Description(value) => request_body.script_info.description = Some(value),
 				EditorUpdate::Module { name, source } => match request_body.script_info.source.modules {
 					None => {
-						request_body.script_info.source.modules = Some(HashMap::from([(*name, *source)]));
+						request_body.script_info.source.modules = Some(IndexMap::from([(*name, *source)]));
 					}
 					Some(ref mut modules) => {
 						modules.insert(*name, *source);
@@ -247,16 +502,18 @@ impl Client {
 		}
 
 		let response = self
-			.client
-			.put(format!("{BASE_URL}/api/script/editor"))
-			.header(
-				"Cookie",
-				format!("session={}", self.secrets.session.clone()),
+			.send(
+				self
+					.client
+					.put(format!("{}/api/script/editor", self.options.base_url))
+					.header(
+						"Cookie",
+						format!("session={}", self.secrets.session.clone()),
+					)
+					.header("User-Agent", USER_AGENT)
+					.header("Content-Type", "application/json")
+					.body(serde_json::to_string(&request_body)?),
 			)
-			.header("User-Agent", USER_AGENT)
-			.header("Content-Type", "application/json")
-			.body(serde_json::to_string(&request_body)?)
-			.send()
 			.await?;
 
 		match response.error_for_status() {
@@ -264,4 +521,47 @@ impl Client {
 			Err(e) => Err(Error::ResponseStatus(e.status().expect("exist"))),
 		}
 	}
+
+	/// Uploads each module with its own request, at most `concurrency` at a time, returning the
+	/// modules that failed along with why.
+	pub async fn set_modules(
+		&self,
+		id: &str,
+		modules: Vec<(String, String)>,
+		concurrency: usize,
+	) -> Vec<(String, Error)> {
+		let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+		let mut uploads = JoinSet::new();
+
+		for (name, source) in modules {
+			let client = self.clone();
+			let id = id.to_owned();
+			let semaphore = semaphore.clone();
+			uploads.spawn(async move {
+				let _permit = semaphore
+					.acquire_owned()
+					.await
+					.expect("semaphore to stay open");
+				let result = client
+					.set_editor(
+						&id,
+						&[EditorUpdate::Module {
+							name: &name,
+							source: &source,
+						}],
+					)
+					.await;
+				(name, result)
+			});
+		}
+
+		let mut failures = Vec::new();
+		while let Some(upload) = uploads.join_next().await {
+			let (name, result) = upload.expect("module upload task panicked");
+			if let Err(e) = result {
+				failures.push((name, e));
+			}
+		}
+		failures
+	}
 }
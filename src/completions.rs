@@ -0,0 +1,114 @@
+use crate::{
+	error::{Context, Error, PathContext},
+	project::write_file,
+};
+use clap_complete::Shell;
+use directories::BaseDirs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Prints the completion script for `shell`, or writes it to `path` (defaulting to where the shell
+/// loads completions from) with `install`. Without `shell`, it's guessed from $SHELL.
+pub async fn completions(
+	shell: Option<Shell>,
+	install: bool,
+	path: Option<PathBuf>,
+	mut command: clap::Command,
+) -> Result<(), Error> {
+	let shell = shell
+		.or_else(Shell::from_env)
+		.context(Error::UnknownShell)?;
+	let name = command.get_name().to_owned();
+	if !install {
+		clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+		return Ok(());
+	}
+
+	let mut script = Vec::new();
+	clap_complete::generate(shell, &mut command, &name, &mut script);
+	let path = match path {
+		Some(path) => path,
+		None => default_path(shell, &name).context(Error::NoCompletionDirectory(shell.to_string()))?,
+	};
+	if let Some(directory) = path
+		.parent()
+		.filter(|parent| !parent.as_os_str().is_empty())
+	{
+		tokio::fs::create_dir_all(directory)
+			.await
+			.with_path(Error::CreateDirectory, directory)?;
+	}
+	write_file(&path, &String::from_utf8_lossy(&script)).await?;
+	info!("installed {shell} completions to {}", path.display());
+
+	let directory = path.parent().unwrap_or(Path::new("."));
+	match shell {
+		Shell::Zsh if !zsh_fpath().await.iter().any(|entry| entry == directory) => warn!(
+			"{} isn't on zsh's $fpath; add `fpath+=({})` to ~/.zshrc before `compinit`",
+			directory.display(),
+			directory.display()
+		),
+		Shell::Elvish => warn!("add `use {name}` to ~/.config/elvish/rc.elv to load the completions"),
+		Shell::Bash | Shell::Fish
+			if default_path(shell, &name).as_deref().and_then(Path::parent) != Some(directory) =>
+		{
+			warn!(
+				"{shell} doesn't load completions from {}; source the file from your shell's startup file",
+				directory.display()
+			)
+		}
+		_ => {}
+	}
+	Ok(())
+}
+
+/// Where `shell` loads the completions of the command `name` from without any setup, or for zsh
+/// and elvish, the directory conventionally used for them.
+fn default_path(shell: Shell, name: &str) -> Option<PathBuf> {
+	let dirs = BaseDirs::new()?;
+	let home = dirs.home_dir();
+	let xdg = |variable: &str, fallback: &str| match std::env::var_os(variable) {
+		Some(directory) if !directory.is_empty() => PathBuf::from(directory),
+		_ => home.join(fallback),
+	};
+	match shell {
+		Shell::Bash => Some(
+			std::env::var_os("BASH_COMPLETION_USER_DIR")
+				.filter(|directory| !directory.is_empty())
+				.map(PathBuf::from)
+				.unwrap_or_else(|| xdg("XDG_DATA_HOME", ".local/share").join("bash-completion"))
+				.join("completions")
+				.join(name),
+		),
+		Shell::Zsh => Some(home.join(".zfunc").join(format!("_{name}"))),
+		Shell::Fish => Some(
+			xdg("XDG_CONFIG_HOME", ".config")
+				.join("fish/completions")
+				.join(format!("{name}.fish")),
+		),
+		Shell::Elvish => Some(
+			xdg("XDG_CONFIG_HOME", ".config")
+				.join("elvish/lib")
+				.join(format!("{name}.elv")),
+		),
+		// PowerShell only loads what the profile sources
+		_ => None,
+	}
+}
+
+/// The directories an interactive zsh searches for functions, or none if zsh couldn't be run.
+async fn zsh_fpath() -> Vec<PathBuf> {
+	let output = tokio::process::Command::new("zsh")
+		.args(["-ic", "print -rl -- $fpath"])
+		.stdin(std::process::Stdio::null())
+		.stderr(std::process::Stdio::null())
+		.output()
+		.await;
+	match output {
+		Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+			.lines()
+			.map(PathBuf::from)
+			.collect(),
+		_ => Vec::new(),
+	}
+}
@@ -0,0 +1,81 @@
+use crate::{error::Error, login::get_config_directory, project::read_file};
+use serde::Deserialize;
+use std::{
+	path::{Path, PathBuf},
+	sync::OnceLock,
+};
+use tracing::{debug, warn};
+
+/// Settings shared by many projects, layered as organization defaults (the file named by
+/// FUMOSYNC_ORG_CONFIG), then the user's defaults.json in the config directory, then each
+/// project's fumosync.json; every layer overrides the ones before it.
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Defaults {
+	/// Root of the fumosclub API, used unless `--base-url`, FUMOSYNC_BASE_URL or the project's
+	/// `baseUrl` is set.
+	#[serde(default)]
+	pub base_url: Option<String>,
+	/// Groups file for projects that don't name one, relative to the project.
+	#[serde(default)]
+	pub groups_file: Option<PathBuf>,
+	#[serde(default)]
+	pub timeout: Option<u64>,
+	#[serde(default)]
+	pub retries: Option<u32>,
+	#[serde(default)]
+	pub formatter: Option<Vec<String>>,
+}
+
+static DEFAULTS: OnceLock<Defaults> = OnceLock::new();
+
+impl Defaults {
+	/// Reads and merges the organization and user layers; missing files are empty layers, and so
+	/// are malformed ones (with a warning), so they can't break every command.
+	pub async fn read() -> Self {
+		let mut defaults = Defaults::default();
+		if let Some(path) = std::env::var_os("FUMOSYNC_ORG_CONFIG") {
+			defaults = defaults.overridden_by(Self::read_layer_or_warn(Path::new(&path)).await);
+		}
+		let user = get_config_directory().join("defaults.json");
+		defaults.overridden_by(Self::read_layer_or_warn(&user).await)
+	}
+
+	async fn read_layer_or_warn(path: &Path) -> Self {
+		Self::read_layer(path).await.unwrap_or_else(|e| {
+			warn!("ignoring the defaults in {}: {e}", path.display());
+			Self::default()
+		})
+	}
+
+	async fn read_layer(path: &Path) -> Result<Self, Error> {
+		if !tokio::fs::try_exists(path).await? {
+			return Ok(Self::default());
+		}
+		let layer: Self = serde_json::from_str(&read_file(path).await?)?;
+		debug!("read defaults from {}: {layer:?}", path.display());
+		Ok(layer)
+	}
+
+	fn overridden_by(self, layer: Self) -> Self {
+		Self {
+			base_url: layer.base_url.or(self.base_url),
+			groups_file: layer.groups_file.or(self.groups_file),
+			timeout: layer.timeout.or(self.timeout),
+			retries: layer.retries.or(self.retries),
+			formatter: layer.formatter.or(self.formatter),
+		}
+	}
+
+	/// Makes these the defaults returned by `shared_defaults`.
+	pub fn install(self) {
+		DEFAULTS
+			.set(self)
+			.expect("defaults to only be installed once");
+	}
+}
+
+/// The organization and user defaults in effect, or none if they weren't installed.
+pub fn shared_defaults() -> &'static Defaults {
+	DEFAULTS.get_or_init(Defaults::default)
+}
@@ -0,0 +1,178 @@
+use crate::{error::Error, project::write_file};
+use serde::Serialize;
+use std::{
+	collections::BTreeMap,
+	fmt,
+	path::PathBuf,
+	sync::{Arc, Mutex, OnceLock},
+};
+use tracing::{
+	field::{Field, Visit},
+	warn, Event, Level, Subscriber,
+};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// Reports problems that don't stop a command by default but should fail it under `--strict`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Diagnostics {
+	pub strict: bool,
+}
+
+impl Diagnostics {
+	/// Logs `message` as a warning of `category`, or returns it as an error in strict mode.
+	pub fn warn(&self, category: Category, message: impl Into<String>) -> Result<(), Error> {
+		let message = message.into();
+		if self.strict {
+			return Err(Error::StrictWarning(message));
+		}
+		match category {
+			Category::Network => warn!(target: "network", "{message}"),
+			Category::Fidelity => warn!(target: "fidelity", "{message}"),
+			Category::Lint => warn!(target: "lint", "{message}"),
+			Category::Safety => warn!(target: "safety", "{message}"),
+			Category::Other => warn!("{message}"),
+		}
+		Ok(())
+	}
+}
+
+/// What a warning is about, given to `warn!` as its target (like `warn!(target: "lint", ...)`).
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+	/// Failed or retried requests.
+	Network,
+	/// Something that won't end up exactly as it is on the other side, like stripped byte order
+	/// marks or skipped blank fields.
+	Fidelity,
+	/// Unused modules and requires of missing ones.
+	Lint,
+	/// Things done to the remote script or local files that deserve attention, like making a
+	/// script public or keeping local edits during a pull.
+	Safety,
+	Other,
+}
+
+impl Category {
+	fn of(target: &str) -> Self {
+		match target {
+			"network" => Category::Network,
+			"fidelity" => Category::Fidelity,
+			"lint" => Category::Lint,
+			"safety" => Category::Safety,
+			_ => Category::Other,
+		}
+	}
+
+	fn name(self) -> &'static str {
+		match self {
+			Category::Network => "network",
+			Category::Fidelity => "fidelity",
+			Category::Lint => "lint",
+			Category::Safety => "safety",
+			Category::Other => "other",
+		}
+	}
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Warning {
+	pub category: Category,
+	pub message: String,
+}
+
+/// What `--warnings-as-json` writes.
+#[derive(Serialize)]
+struct WarningsReport<'a> {
+	counts: BTreeMap<Category, usize>,
+	warnings: &'a [Warning],
+}
+
+/// Collects every warning logged (besides the `notice` ones, like the beta notice) so they can be
+/// summarized once the command is done.
+#[derive(Clone, Default)]
+pub struct Warnings {
+	warnings: Arc<Mutex<Vec<Warning>>>,
+}
+
+static WARNINGS: OnceLock<(Warnings, Option<PathBuf>)> = OnceLock::new();
+
+struct MessageVisitor(Option<String>);
+
+impl Visit for MessageVisitor {
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		if field.name() == "message" {
+			self.0 = Some(format!("{value:?}"));
+		}
+	}
+}
+
+impl<S: Subscriber> Layer<S> for Warnings {
+	fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+		let metadata = event.metadata();
+		if *metadata.level() != Level::WARN || metadata.target() == "notice" {
+			return;
+		}
+		let mut visitor = MessageVisitor(None);
+		event.record(&mut visitor);
+		if let Some(message) = visitor.0 {
+			self
+				.warnings
+				.lock()
+				.expect("warnings not to be poisoned")
+				.push(Warning {
+					category: Category::of(metadata.target()),
+					message,
+				});
+		}
+	}
+}
+
+impl Warnings {
+	/// Makes these the warnings `report_warnings` summarizes, also writing them to `json` if given.
+	pub fn install(&self, json: Option<PathBuf>) {
+		let _ = WARNINGS.set((self.clone(), json));
+	}
+}
+
+/// Prints the collected warnings grouped by category to stderr when there was more than one, and
+/// writes them to the `--warnings-as-json` file.
+pub async fn report_warnings() -> Result<(), Error> {
+	let Some((warnings, json)) = WARNINGS.get() else {
+		return Ok(());
+	};
+	let warnings = warnings
+		.warnings
+		.lock()
+		.expect("warnings not to be poisoned")
+		.clone();
+	let mut grouped: BTreeMap<Category, Vec<&str>> = BTreeMap::new();
+	for warning in &warnings {
+		grouped
+			.entry(warning.category)
+			.or_default()
+			.push(&warning.message);
+	}
+
+	if warnings.len() > 1 {
+		eprintln!("{} warnings:", warnings.len());
+		for (category, messages) in &grouped {
+			eprintln!("  {} ({})", category.name(), messages.len());
+			for message in messages {
+				eprintln!("    {message}");
+			}
+		}
+	}
+
+	if let Some(path) = json {
+		let report = WarningsReport {
+			counts: grouped
+				.iter()
+				.map(|(category, messages)| (*category, messages.len()))
+				.collect(),
+			warnings: &warnings,
+		};
+		write_file(path, &serde_json::to_string_pretty(&report)?).await?;
+	}
+	Ok(())
+}
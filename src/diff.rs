@@ -0,0 +1,285 @@
+use crate::{
+	client::{Client, EditorUpdate},
+	error::{Error, PathContext},
+	project::{write_file, LocalProject, PushOptions},
+};
+use similar::{ChangeTag, TextDiff};
+use std::{
+	collections::HashSet,
+	path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// A file whose remote and local contents differ; when comparing two scripts, `remote` is the
+/// first one and `local` the second.
+struct FileDiff {
+	path: PathBuf,
+	remote: String,
+	local: String,
+}
+
+impl FileDiff {
+	/// Lines added and removed going from the remote contents to the local ones.
+	fn counts(&self) -> (usize, usize) {
+		let diff = TextDiff::from_lines(&self.remote, &self.local);
+		diff
+			.iter_all_changes()
+			.fold((0, 0), |(added, removed), change| match change.tag() {
+				ChangeTag::Insert => (added + 1, removed),
+				ChangeTag::Delete => (added, removed + 1),
+				ChangeTag::Equal => (added, removed),
+			})
+	}
+}
+
+/// Prints what pushing the project in the current directory would change on the remote script,
+/// as unified diffs from remote to local, or with `stat` as per-file counts of changed lines.
+/// Modules that only exist on one side are shown as entirely added or removed. With `git_ref`, the
+/// local side is the project as committed at that ref instead of the working tree.
+pub async fn diff_project(stat: bool, git_ref: Option<&str>, client: &Client) -> Result<(), Error> {
+	let Some(git_ref) = git_ref else {
+		return diff_directory(Path::new("."), stat, client).await;
+	};
+
+	// a fresh directory only this process can know about, removed again when dropped
+	let scratch = tempfile::Builder::new()
+		.prefix("fumosync-diff-")
+		.tempdir()?;
+	let result = match export_ref(git_ref, scratch.path()).await {
+		Ok(()) => diff_directory(scratch.path(), stat, client).await,
+		Err(e) => Err(e),
+	};
+	if let Err(io_error) = scratch.close() {
+		warn!("failed cleaning up the diff directory: {io_error}");
+	}
+	result
+}
+
+/// Runs git in the current directory, returning what it printed.
+pub async fn git(arguments: &[&str]) -> Result<Vec<u8>, Error> {
+	let output = tokio::process::Command::new("git")
+		.args(arguments)
+		.output()
+		.await?;
+	if !output.status.success() {
+		return Err(Error::Git(
+			arguments.join(" "),
+			String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+		));
+	}
+	Ok(output.stdout)
+}
+
+/// Paths (relative to the current directory) of the files under it that differ from how they were
+/// committed at `git_ref`, including untracked files that aren't ignored.
+pub async fn changed_since(git_ref: &str) -> Result<HashSet<PathBuf>, Error> {
+	let changed = git(&["diff", "--name-only", "-z", "--relative", git_ref, "--"]).await?;
+	let untracked = git(&["ls-files", "--others", "--exclude-standard", "-z"]).await?;
+	Ok(
+		changed
+			.split(|byte| *byte == 0)
+			.chain(untracked.split(|byte| *byte == 0))
+			.filter(|path| !path.is_empty())
+			.map(|path| PathBuf::from(&*String::from_utf8_lossy(path)))
+			.collect(),
+	)
+}
+
+/// Writes the files under the current directory as committed at `git_ref` into `directory`.
+async fn export_ref(git_ref: &str, directory: &Path) -> Result<(), Error> {
+	let listing = git(&["ls-tree", "-r", "-z", "--name-only", git_ref]).await?;
+	for path in listing
+		.split(|byte| *byte == 0)
+		.filter(|path| !path.is_empty())
+	{
+		let path = String::from_utf8_lossy(path);
+		let contents = git(&["show", &format!("{git_ref}:./{path}")]).await?;
+
+		let destination = directory.join(&*path);
+		if let Some(parent) = destination.parent() {
+			tokio::fs::create_dir_all(parent)
+				.await
+				.with_path(Error::CreateDirectory, parent)?;
+		}
+		write_file(&destination, &String::from_utf8_lossy(&contents)).await?;
+	}
+	Ok(())
+}
+
+async fn diff_directory(root: &Path, stat: bool, client: &Client) -> Result<(), Error> {
+	let project = LocalProject::read(root, &PushOptions::default()).await?;
+	let remote = client
+		.get_editor(&project.configuration.script_id)
+		.await?
+		.script_info;
+
+	let mut files = Vec::new();
+	for update in project.updates() {
+		if update.is_applied_to(&remote) {
+			continue;
+		}
+
+		let remote_text = match &update {
+			EditorUpdate::Description(_) => remote.description.as_str(),
+			EditorUpdate::MainSource(_) => remote.source.main.as_str(),
+			EditorUpdate::Module { name, .. } => remote
+				.source
+				.modules
+				.get(*name)
+				.map(String::as_str)
+				.unwrap_or_default(),
+			EditorUpdate::Name(_) | EditorUpdate::Whitelist(_) | EditorUpdate::Publicity(_) => {
+				println!("{} differs", update.label());
+				continue;
+			}
+		};
+		files.push(FileDiff {
+			path: relative(root, project.source_of(&update)),
+			remote: remote_text.to_owned(),
+			local: update.text().unwrap_or_default().to_owned(),
+		});
+	}
+
+	for (name, source) in &remote.source.modules {
+		if !project.modules.iter().any(|module| &module.name == name) {
+			files.push(FileDiff {
+				path: relative(root, project.configuration.module_path(root, name)),
+				remote: source.clone(),
+				local: String::new(),
+			});
+		}
+	}
+
+	print_files(&files, stat, ("remote", "local"));
+	Ok(())
+}
+
+/// Prints how the script `to` differs from the script `from`, both as stored remotely: its name,
+/// publicity and whitelist, then its sources like `diff_project` does. Nothing is written.
+pub async fn compare_scripts(
+	from: &str,
+	to: &str,
+	stat: bool,
+	client: &Client,
+) -> Result<(), Error> {
+	let (old, new) = tokio::try_join!(client.get_editor(from), client.get_editor(to))?;
+	let (old, new) = (old.script_info, new.script_info);
+
+	if old.name != new.name {
+		println!("name: {} -> {}", old.name, new.name);
+	}
+	if old.is_public != new.is_public {
+		println!("public: {} -> {}", old.is_public, new.is_public);
+	}
+	for entry in new
+		.whitelist
+		.iter()
+		.filter(|entry| !old.whitelist.contains(entry))
+	{
+		println!("whitelist + {entry}");
+	}
+	for entry in old
+		.whitelist
+		.iter()
+		.filter(|entry| !new.whitelist.contains(entry))
+	{
+		println!("whitelist - {entry}");
+	}
+
+	let mut files = Vec::new();
+	let mut push = |path: String, remote: &str, local: &str| {
+		if remote != local {
+			files.push(FileDiff {
+				path: PathBuf::from(path),
+				remote: remote.to_owned(),
+				local: local.to_owned(),
+			});
+		}
+	};
+	push("description".to_owned(), &old.description, &new.description);
+	push("main".to_owned(), &old.source.main, &new.source.main);
+	for (name, source) in &old.source.modules {
+		let other = new.source.modules.get(name).map_or("", String::as_str);
+		push(format!("modules/{name}"), source, other);
+	}
+	for (name, source) in &new.source.modules {
+		if !old.source.modules.contains_key(name) {
+			push(format!("modules/{name}"), "", source);
+		}
+	}
+
+	print_files(&files, stat, (from, to));
+	Ok(())
+}
+
+/// Prints `files` as unified diffs with paths prefixed by `labels`, or with `stat` as a diffstat.
+fn print_files(files: &[FileDiff], stat: bool, labels: (&str, &str)) {
+	match stat {
+		true => print_stat(files),
+		false => {
+			for file in files {
+				let path = file.path.display().to_string();
+				print!(
+					"{}",
+					TextDiff::from_lines(&file.remote, &file.local)
+						.unified_diff()
+						.header(
+							&format!("{}/{path}", labels.0),
+							&format!("{}/{path}", labels.1)
+						)
+				);
+			}
+		}
+	}
+}
+
+fn relative(root: &Path, path: PathBuf) -> PathBuf {
+	path
+		.strip_prefix(root)
+		.map(Path::to_path_buf)
+		.unwrap_or(path)
+}
+
+/// Prints a git style diffstat: one ` path | changes +++--` line per file, then the totals.
+fn print_stat(files: &[FileDiff]) {
+	const BAR_WIDTH: usize = 40;
+
+	let counts: Vec<(usize, usize)> = files.iter().map(FileDiff::counts).collect();
+	let paths: Vec<String> = files
+		.iter()
+		.map(|file| file.path.display().to_string())
+		.collect();
+	let path_width = paths.iter().map(String::len).max().unwrap_or(0);
+	let largest = counts
+		.iter()
+		.map(|(added, removed)| added + removed)
+		.max()
+		.unwrap_or(0);
+
+	for (path, (added, removed)) in paths.iter().zip(&counts) {
+		// scale the bar down only when the largest change wouldn't fit
+		let (added_bar, removed_bar) = match largest > BAR_WIDTH {
+			true => (
+				(added * BAR_WIDTH).div_ceil(largest),
+				(removed * BAR_WIDTH).div_ceil(largest),
+			),
+			false => (*added, *removed),
+		};
+		println!(
+			" {path:path_width$} | {:>5} {}{}",
+			added + removed,
+			"+".repeat(added_bar),
+			"-".repeat(removed_bar)
+		);
+	}
+
+	let (added, removed) = counts
+		.iter()
+		.fold((0, 0), |(total_added, total_removed), (added, removed)| {
+			(total_added + added, total_removed + removed)
+		});
+	println!(
+		" {} files changed, {added} insertions(+), {removed} deletions(-)",
+		files.len()
+	);
+}
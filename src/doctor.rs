@@ -0,0 +1,122 @@
+use crate::{
+	client::{Client, ClientOptions},
+	error::Error,
+	login::get_session_secrets,
+	project::{
+		configuration_path, read_configuration, LocalProject, PushOptions, PLACEHOLDER_SCRIPT_ID,
+	},
+};
+use std::{fmt::Display, path::Path};
+
+#[derive(Default)]
+struct Checklist {
+	failures: usize,
+}
+
+impl Checklist {
+	fn pass(&self, check: &str, detail: impl Display) {
+		println!("✓ {check}: {detail}");
+	}
+
+	fn fail(&mut self, check: &str, detail: impl Display, hint: &str) {
+		self.failures += 1;
+		println!("✗ {check}: {detail}\n    {hint}");
+	}
+}
+
+/// Checks the session, the project in the current directory and the connection to fumosclub,
+/// printing a pass/fail line with a hint for every check. Nothing is written or pushed.
+pub async fn doctor(client_options: ClientOptions) -> Result<(), Error> {
+	let mut checklist = Checklist::default();
+	let root = Path::new(".");
+
+	let client = match get_session_secrets().await {
+		Ok(secrets) => {
+			checklist.pass("session", format!("valid until {}", secrets.expires));
+			Some(Client::new(secrets, client_options))
+		}
+		Err(e) => {
+			checklist.fail("session", e, "run `fumosync login` to sign in again");
+			None
+		}
+	};
+
+	if let Some(client) = &client {
+		match client.get_details().await {
+			Ok(details) => checklist.pass("server", format!("signed in as {}", details.name)),
+			Err(e) => checklist.fail(
+				"server",
+				e,
+				"check your connection (and FUMOSYNC_BASE_URL), or log in again if the session was revoked",
+			),
+		}
+	}
+
+	let configuration = match read_configuration(root).await {
+		Ok(configuration) => {
+			checklist.pass(
+				"config",
+				format!("{} parses", configuration_path(root).display()),
+			);
+			Some(configuration)
+		}
+		Err(e @ Error::NotAFumosyncProject(_)) => {
+			checklist.fail(
+				"config",
+				e,
+				"run doctor from the project directory to check its files too",
+			);
+			None
+		}
+		Err(e) => {
+			checklist.fail("config", e, "fix the syntax error in the configuration");
+			None
+		}
+	};
+
+	let Some(configuration) = configuration else {
+		return finish(checklist);
+	};
+
+	match LocalProject::read(root, &PushOptions::default()).await {
+		Ok(project) => checklist.pass(
+			"files",
+			format!(
+				"{} and {} modules found",
+				project.main_path.display(),
+				project.modules.len()
+			),
+		),
+		Err(e) => checklist.fail(
+			"files",
+			e,
+			"restore the missing file, or run `fumosync init` in a new directory to see the expected layout",
+		),
+	}
+
+	if configuration.script_id == PLACEHOLDER_SCRIPT_ID {
+		checklist.fail(
+			"script",
+			"the project isn't linked to a script",
+			"set scriptId in the configuration",
+		);
+	} else if let Some(client) = &client {
+		match client.get_editor(&configuration.script_id).await {
+			Ok(_) => checklist.pass("script", format!("{} is editable", configuration.script_id)),
+			Err(e) => checklist.fail(
+				"script",
+				e,
+				"check that scriptId is right and that this account can edit the script",
+			),
+		}
+	}
+
+	finish(checklist)
+}
+
+fn finish(checklist: Checklist) -> Result<(), Error> {
+	match checklist.failures {
+		0 => Ok(()),
+		failures => Err(Error::ChecksFailed(failures)),
+	}
+}
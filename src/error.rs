@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
@@ -17,18 +17,106 @@ pub enum Error {
 	ReadFile(PathBuf, io::Error),
 	#[error("failed reading directory: {0}; {1}")]
 	ReadDirectory(PathBuf, io::Error),
+	#[error("failed removing file: {0}; {1}")]
+	RemoveFile(PathBuf, io::Error),
+	#[error("failed renaming file: {0}; {1}")]
+	RenameFile(PathBuf, io::Error),
+	#[error("failed removing directory: {0}; {1}")]
+	RemoveDirectory(PathBuf, io::Error),
+	#[error("{0} should be a directory, but it's a file")]
+	NotADirectory(PathBuf),
+	#[error("{0} isn't a fumosync project (no fumosync.json or fumosync.toml); run `fumosync init` or `fumosync pull` first, or cd into your project")]
+	NotAFumosyncProject(PathBuf),
 	#[error("failed initializing project: {0}")]
 	ProjectDidntInitialize(Box<Error>),
 	#[error("reqwest error: {0}")]
 	Reqwest(#[from] reqwest::Error),
 	#[error("serde error: {0}")]
 	Serde(#[from] serde_json::Error),
+	#[error("toml error: {0}")]
+	TomlDeserialize(#[from] toml::de::Error),
+	#[error("toml error: {0}")]
+	TomlSerialize(#[from] toml::ser::Error),
 	#[error("generic io error: {0}")]
 	Io(#[from] io::Error),
 	#[error("got error response status: {0}")]
 	ResponseStatus(StatusCode),
 	#[error("secrets expired at {0}")]
 	SecretsExpired(DateTime<Utc>),
+	#[error("the main source {0} doesn't exist; create it, point `files.main` in fumosync.json at another file, or pass --allow-empty-main")]
+	MissingMainSource(PathBuf),
+	#[error("the description file {0} doesn't exist; create it or point `descriptionFile` in the configuration at another file")]
+	MissingDescription(PathBuf),
+	#[error("script {0} already has content; pass --force to overwrite it")]
+	ForkTargetNotEmpty(String),
+	#[error("failed uploading modules: {}", .0.join(", "))]
+	ModuleUploadsFailed(Vec<String>),
+	#[error("{0} of {1} pulls failed")]
+	PullsFailed(usize, usize),
+	#[error("{0} checks failed")]
+	ChecksFailed(usize),
+	#[error("{0} files didn't survive a pull followed by a push")]
+	RoundtripDiverged(usize),
+	#[error("module name {0} is used by both {1} and {2}")]
+	ModuleNameCollision(String, PathBuf, PathBuf),
+	#[error("{0} is named like a server or client script, but fumosclub modules are all module scripts; rename it to end in .luau or .module.luau")]
+	UnsupportedScriptKind(PathBuf),
+	#[error("both sides changed since the last push or pull (locally: {}; remotely: {}); pass --prefer local or --prefer remote", .0.join(", "), .1.join(", "))]
+	SyncConflict(Vec<String>, Vec<String>),
+	#[error("remote modules {0} and {1} only differ by case, so they'd share a file on case-insensitive filesystems; pass --case-collisions suffix to write them to different files")]
+	CaseCollision(String, String),
+	#[error(
+		"can't write the remote {0} to {1}: {2} is in the way; move it or map the {0} to another path"
+	)]
+	PathConflict(String, PathBuf, PathBuf),
+	#[error("there's no module named {0}")]
+	UnknownModule(String),
+	#[error("script {1} has no module named {0}")]
+	UnknownRemoteModule(String, String),
+	#[error("module {0} already exists at {1}")]
+	ModuleAlreadyExists(String, PathBuf),
+	#[error("sources total {0} bytes, over the budget of {1} KB")]
+	SizeBudgetExceeded(usize, u64),
+	#[error("found {0} modules, more than the limit of {1}; check the module directories, or raise the limit with --max-modules")]
+	TooManyModules(usize, usize),
+	#[error("push took longer than the budget of {0} seconds")]
+	TimeBudgetExceeded(u64),
+	#[error("there's no environment named {0}; the configuration has {}", match .1.is_empty() { true => "none".to_owned(), false => .1.join(", ") })]
+	UnknownEnvironment(String, Vec<String>),
+	#[error("whitelist group @{0} isn't defined in {1}")]
+	UndefinedGroup(String, PathBuf),
+	#[error(
+		"push was interrupted before the server responded; the remote script may be partially updated"
+	)]
+	PushInterrupted,
+	#[error("logging in failed: {0}")]
+	LoginFailed(String),
+	#[error("cancelled")]
+	Cancelled,
+	#[error("`git {0}` failed: {1}")]
+	Git(String, String),
+	#[error("not making script {0} public without confirmation; pass --confirm-publicity")]
+	PublicityNotConfirmed(String),
+	#[error("there's no terminal to ask on; {0}")]
+	NotInteractive(String),
+	#[error(
+		"couldn't tell which shell this is from $SHELL; name it, like `fumosync completions zsh`"
+	)]
+	UnknownShell,
+	#[error("{0} has no standard place for completions; pass --path")]
+	NoCompletionDirectory(String),
+	#[error("{0} (failing because of --strict)")]
+	StrictWarning(String),
+}
+
+impl Error {
+	/// Status the process exits with when this error ends it.
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Error::StrictWarning(_) => 2,
+			_ => 1,
+		}
+	}
 }
 
 /// Custom context trait to convert a Option to a Result.
@@ -55,3 +143,23 @@ impl<T, E> Context<T, E> for Option<T> {
 		}
 	}
 }
+
+/// Attaches the path an io operation was working on, using the given variant (such as
+/// `Error::ReadFile`) to describe what failed.
+pub trait PathContext<T> {
+	fn with_path<P: AsRef<Path>>(
+		self,
+		variant: fn(PathBuf, io::Error) -> Error,
+		path: P,
+	) -> Result<T, Error>;
+}
+
+impl<T> PathContext<T> for Result<T, io::Error> {
+	fn with_path<P: AsRef<Path>>(
+		self,
+		variant: fn(PathBuf, io::Error) -> Error,
+		path: P,
+	) -> Result<T, Error> {
+		self.map_err(|io_error| variant(path.as_ref().to_path_buf(), io_error))
+	}
+}
@@ -0,0 +1,72 @@
+use crate::error::Error;
+use std::{
+	future::Future,
+	sync::{
+		atomic::{AtomicBool, AtomicUsize, Ordering},
+		OnceLock,
+	},
+};
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// How many requests are guarded by `finish_despite_interrupt` right now.
+static GUARDED: AtomicUsize = AtomicUsize::new(0);
+/// Whether Ctrl-C was pressed while a request was guarded.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+/// Woken by a second Ctrl-C, abandoning the guarded requests.
+static ABANDON: OnceLock<Notify> = OnceLock::new();
+
+fn abandon() -> &'static Notify {
+	ABANDON.get_or_init(Notify::new)
+}
+
+/// Handles Ctrl-C for the whole process: it exits right away unless a request is guarded, since
+/// listening for Ctrl-C at all replaces the default of exiting.
+pub fn listen() {
+	tokio::spawn(async {
+		loop {
+			if tokio::signal::ctrl_c().await.is_err() {
+				return;
+			}
+			if GUARDED.load(Ordering::SeqCst) == 0 {
+				std::process::exit(130);
+			}
+			match INTERRUPTED.swap(true, Ordering::SeqCst) {
+				true => abandon().notify_waiters(),
+				false => warn!(
+					target: "safety",
+					"waiting for the request in flight to finish; press Ctrl-C again to abandon it"
+				),
+			}
+		}
+	});
+}
+
+/// Whether Ctrl-C was pressed during a guarded request, so whatever would come next shouldn't
+/// be sent.
+pub fn interrupted() -> bool {
+	INTERRUPTED.load(Ordering::SeqCst)
+}
+
+struct Guard;
+
+impl Drop for Guard {
+	fn drop(&mut self) {
+		GUARDED.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+/// Drives an in-flight request to completion even if Ctrl-C is pressed once, so the remote
+/// isn't left in an unknown state; a second Ctrl-C abandons the request. Only guard a single
+/// request (or requests sent together), and check `interrupted` before sending the next one.
+pub async fn finish_despite_interrupt<F: Future<Output = Result<(), Error>>>(
+	request: F,
+) -> Result<(), Error> {
+	GUARDED.fetch_add(1, Ordering::SeqCst);
+	let _guard = Guard;
+	let abandoned = abandon().notified();
+	tokio::select! {
+		result = request => result,
+		_ = abandoned => Err(Error::PushInterrupted),
+	}
+}
@@ -1,16 +1,22 @@
 use crate::{
-	error::Error,
+	client::ClientOptions,
+	error::{Context, Error},
 	project::{read_file, write_file},
 };
 use chrono::serde::ts_seconds;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use directories::ProjectDirs;
 use headless_chrome::protocol::cdp::Network::Cookie;
 use headless_chrome::{
-	browser::default_executable, protocol::cdp::Target::CreateTarget, Browser, LaunchOptionsBuilder,
+	browser::{default_executable, tab::Tab},
+	protocol::cdp::Target::CreateTarget,
+	Browser, LaunchOptionsBuilder,
 };
 use serde::{Deserialize, Serialize};
-use std::{ffi::OsStr, path::PathBuf};
+use std::{ffi::OsStr, io::IsTerminal, path::PathBuf, sync::OnceLock};
+use tracing::{info, warn};
+
+const LOGIN_URL: &str = "https://fumosclubv1.vercel.app/";
 
 pub fn get_config_directory() -> PathBuf {
 	ProjectDirs::from("com", "techs-sus", "fumosync")
@@ -20,7 +26,7 @@ pub fn get_config_directory() -> PathBuf {
 }
 
 /// secrets.json
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Secrets {
 	pub session: String,
 	#[serde(with = "ts_seconds")]
@@ -35,7 +41,32 @@ pub async fn save_session_secrets(secrets: Secrets) -> Result<(), Error> {
 	.await
 }
 
+static SESSION_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Reads the session from `path` (`--session-file`) instead of the one saved by `fumosync login`.
+pub fn use_session_file(path: PathBuf) {
+	SESSION_FILE
+		.set(path)
+		.expect("session file to only be set once");
+}
+
+/// Returns the session to authenticate with. A session in FUMOSYNC_SESSION (which may come from a
+/// .env file) takes precedence, then the contents of the `--session-file`, then the session saved
+/// by `fumosync login`.
 pub async fn get_session_secrets() -> Result<Secrets, Error> {
+	let session = match (std::env::var("FUMOSYNC_SESSION"), SESSION_FILE.get()) {
+		(Ok(session), _) => Some(session),
+		(Err(_), Some(path)) => Some(read_file(path).await?.trim_end().to_owned()),
+		(Err(_), None) => None,
+	};
+	if let Some(session) = session {
+		// the expiry of a session handed over like this isn't known; let the server decide
+		return Ok(Secrets {
+			session,
+			expires: DateTime::<Utc>::MAX_UTC,
+		});
+	}
+
 	let secrets_string = read_file(get_config_directory().join("secrets.json")).await?;
 	let secrets: Secrets = serde_json::from_str(&secrets_string)?;
 	if secrets.expires <= Utc::now() {
@@ -44,22 +75,60 @@ pub async fn get_session_secrets() -> Result<Secrets, Error> {
 	Ok(secrets)
 }
 
-/// Returns a session cookie.
-pub fn use_browser_token() -> Secrets {
-	let browser = Browser::new(
-		LaunchOptionsBuilder::default()
-			.headless(false)
-			.path(Some(
-				default_executable().expect("failed finding chrome/chromium executable"),
-			))
-			.build()
-			.expect("failed building launch options"),
-	)
-	.expect("failed creating browser");
+/// Like `get_session_secrets`, but renews a saved session that has expired or expires within the
+/// hour by logging in again, if there's a terminal to do that from. fumosclub has no way to
+/// refresh a session, so a new login is the only way to renew one. When the login fails, a session
+/// that hasn't expired yet is still used.
+pub async fn get_renewed_session_secrets(options: &ClientOptions) -> Result<Secrets, Error> {
+	let result = get_session_secrets().await;
+	let expires = match &result {
+		Ok(secrets) if secrets.expires - Utc::now() > TimeDelta::hours(1) => return result,
+		Ok(secrets) => secrets.expires,
+		Err(Error::SecretsExpired(expires)) => *expires,
+		Err(_) => return result,
+	};
+
+	if !std::io::stdin().is_terminal() {
+		if result.is_ok() {
+			warn!("the session expires at {expires}; run `fumosync login` to renew it");
+		}
+		return result;
+	}
+
+	info!("the session expires at {expires}, logging in again");
+	let options = options.clone();
+	match tokio::task::spawn_blocking(move || use_browser_token(&options)).await {
+		Ok(Ok(secrets)) => {
+			save_session_secrets(secrets.clone()).await?;
+			Ok(secrets)
+		}
+		Ok(Err(e)) => {
+			warn!(target: "network", "logging in again failed: {e}");
+			result
+		}
+		Err(_) => {
+			warn!(target: "network", "logging in again failed");
+			result
+		}
+	}
+}
+
+/// Logs in through a browser window and returns the session cookie. Loading the login page is
+/// retried like API requests are (per `options`); a login that ends without a session isn't.
+pub fn use_browser_token(options: &ClientOptions) -> Result<Secrets, Error> {
+	let executable = default_executable()
+		.map_err(|e| Error::LoginFailed(format!("couldn't find chrome or chromium: {e}")))?;
+	let launch_options = LaunchOptionsBuilder::default()
+		.headless(false)
+		.path(Some(executable))
+		.build()
+		.map_err(|e| Error::LoginFailed(format!("couldn't configure the browser: {e}")))?;
+	let browser = Browser::new(launch_options)
+		.map_err(|e| Error::LoginFailed(format!("couldn't start the browser: {e}")))?;
 
 	let tab = browser
 		.new_tab_with_options(CreateTarget {
-			url: "https://fumosclubv1.vercel.app/".to_owned(),
+			url: LOGIN_URL.to_owned(),
 			width: None,
 			height: None,
 			browser_context_id: None,
@@ -67,11 +136,25 @@ pub fn use_browser_token() -> Secrets {
 			new_window: None,
 			background: None,
 		})
-		.expect("failed creating new tab");
+		.map_err(|e| Error::LoginFailed(format!("couldn't open a tab: {e}")))?;
 
-	tab
-		.wait_until_navigated()
-		.expect("failed waiting for new tab to navigate");
+	let mut attempt = 0;
+	while let Err(e) = tab.wait_until_navigated() {
+		if attempt >= options.retries {
+			return Err(Error::LoginFailed(format!(
+				"network failed loading {LOGIN_URL}: {e}"
+			)));
+		}
+		attempt += 1;
+		warn!(
+			target: "network",
+			"loading {LOGIN_URL} failed, retrying ({attempt}/{}): {e}",
+			options.retries
+		);
+		std::thread::sleep(options.backoff.delay(attempt));
+		// a failed navigation leaves the tab on an error page
+		tab.navigate_to(LOGIN_URL).ok();
+	}
 
 	// cleans up tabs which Magically existed
 	let id = tab.get_target_id();
@@ -86,21 +169,22 @@ pub fn use_browser_token() -> Secrets {
 			}
 		});
 
-	while tab
-		.get_cookies()
-		.expect("failed getting cookies")
-		.is_empty()
-	{}
+	let cookies = |tab: &Tab| {
+		tab
+			.get_cookies()
+			.map_err(|e| Error::LoginFailed(format!("the browser went away before logging in: {e}")))
+	};
+	while cookies(&tab)?.is_empty() {}
 
-	let session: Cookie = tab
-		.get_cookies()
-		.expect("failed getting cookies")
+	let session: Cookie = cookies(&tab)?
 		.into_iter()
 		.find(|cookie| cookie.name == "session")
-		.expect("failed finding session cookie");
-	Secrets {
+		.context(Error::LoginFailed(
+			"fumosclub rejected the login; it didn't hand out a session".to_owned(),
+		))?;
+	Ok(Secrets {
 		session: session.value,
 		expires: DateTime::from_timestamp(session.expires as i64, 0u32)
 			.expect("failed creating DateTime<Utc> for session expiry"),
-	}
+	})
 }
@@ -1,15 +1,47 @@
 mod client;
+mod completions;
+mod defaults;
+mod diagnostics;
+mod diff;
+mod doctor;
 mod error;
+mod interrupt;
 mod login;
+mod modules;
 mod project;
+mod prompt;
+mod state;
+mod sync;
+mod timings;
+mod workspace;
 
-use clap::{Parser, Subcommand};
-use client::Client;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use client::{Backoff, Client, ClientOptions};
+use completions::completions;
+use defaults::{shared_defaults, Defaults};
+use diagnostics::{report_warnings, Diagnostics, Warnings};
+use diff::{compare_scripts, diff_project};
+use doctor::doctor;
 use error::Error;
-use login::{get_config_directory, get_session_secrets, save_session_secrets, use_browser_token};
-use project::{init, pull_project, push_project, read_configuration};
-use std::path::PathBuf;
-use tracing::warn;
+use login::{get_config_directory, save_session_secrets, use_browser_token, use_session_file};
+use modules::{list_modules, listing_schema, pull_module, rename_module};
+use project::{
+	configuration_schema, fork_project, init, init_git, override_configuration_path, pull_project,
+	push_project, read_configuration, repair, unlink_project, verify_roundtrip, ConfigFormat,
+	PullOptions, PushOptions,
+};
+use std::{
+	io::IsTerminal,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+use sync::{status, sync, Side};
+use timings::Timings;
+use tracing::{debug, info, warn, Level};
+use tracing_subscriber::{
+	filter::Targets, fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt,
+};
+use workspace::pull_all;
 
 #[derive(Subcommand, Clone, Debug)]
 pub enum Command {
@@ -18,16 +50,119 @@ pub enum Command {
 	/// Shows infomation about the current fumosclub account.
 	View,
 	/// Initializes a project in the directory.
-	Init { project_directory: PathBuf },
+	Init {
+		project_directory: PathBuf,
+		/// Script name; defaults to the name of the directory.
+		#[arg(long, conflicts_with = "repair")]
+		name: Option<String>,
+		/// Also create a git repository with the new files committed.
+		#[arg(long)]
+		git: bool,
+		/// Only recreate the scaffold files missing from an existing project.
+		#[arg(long, conflicts_with = "git")]
+		repair: bool,
+	},
 	/// Lists all projects under the logged in fumosclub account.
 	List,
-	/// Pulls down a script from fumosclub (the script must be editable).
+	/// Pulls down a script from fumosclub (the script must be editable). Pulling into an existing
+	/// project refreshes it.
 	Pull {
 		script_id: String,
 		project_directory: PathBuf,
+		/// Script name to write to the configuration instead of the remote one; the next push
+		/// renames the remote script to it.
+		#[arg(long)]
+		name: Option<String>,
+		#[command(flatten)]
+		options: PullOptions,
+	},
+	/// Writes one module of the linked remote script into the current project, leaving every other
+	/// file alone.
+	PullModule { name: String },
+	/// Refreshes every project listed in fumosync.workspace.json from its linked script.
+	PullAll {
+		/// How many projects to pull at once.
+		#[arg(long, default_value_t = 4)]
+		jobs: usize,
+		#[command(flatten)]
+		options: PullOptions,
 	},
 	/// Push the script in current directory to fumosclub using the fumosync.json file.
-	Push,
+	Push(PushOptions),
+	/// Pushes or pulls the current project, whichever brings over what changed since the last push
+	/// or pull; refuses and shows the diff when both sides changed.
+	Sync {
+		/// Side whose changes win when both changed.
+		#[arg(long, value_enum)]
+		prefer: Option<Side>,
+	},
+	/// Lists the sources of the current project with when they were last modified and whether they
+	/// changed locally or remotely since the last push or pull.
+	Status,
+	/// Pushes the current project to another, empty script and copies it to a new directory linked
+	/// to that script.
+	Fork {
+		script_id: String,
+		project_directory: PathBuf,
+		/// Overwrite the target script even if it already has content.
+		#[arg(long)]
+		force: bool,
+		/// Make the target script public without asking first, when the project is public.
+		#[arg(long)]
+		confirm_publicity: bool,
+	},
+	/// Shows what pushing the current project would change, as a diff from the remote script.
+	Diff {
+		/// Only print how many lines changed in each file.
+		#[arg(long)]
+		stat: bool,
+		/// Compare against the project as committed at this git ref instead of the working tree.
+		#[arg(long = "ref", value_name = "REF")]
+		git_ref: Option<String>,
+		/// Compare the linked remote script with this other remote script instead of with the
+		/// project; no local files are read besides the configuration.
+		#[arg(long, value_name = "SCRIPT_ID", conflicts_with = "git_ref")]
+		compare_with: Option<String>,
+	},
+	/// Manages the modules of the current project.
+	Modules {
+		#[command(subcommand)]
+		command: ModulesCommand,
+	},
+	/// Detaches the current project from its fumosclub script, keeping all local files.
+	Unlink,
+	/// Checks the session, project and connection to fumosclub without changing anything.
+	Doctor,
+	/// Pulls a script into a temporary directory and checks that pushing it back would change nothing.
+	VerifyRoundtrip { script_id: String },
+	/// Prints what the server returns for a script's editor, for debugging and bug reports.
+	#[command(hide = true)]
+	DumpEditor {
+		/// Defaults to the script the current project is linked to.
+		script_id: Option<String>,
+		/// Print the body exactly as received instead of pretty printing it.
+		#[arg(long)]
+		raw: bool,
+	},
+	/// Prints a JSON Schema for fumosync.json, for editors (through `$schema`) and CI to validate
+	/// configurations against, or for other JSON fumosync prints.
+	Schema {
+		#[arg(value_enum, default_value_t)]
+		of: SchemaKind,
+	},
+	/// Prints the completion script for a shell, or installs it with --install.
+	Completions {
+		/// Defaults to the shell named by $SHELL.
+		#[arg(value_enum)]
+		shell: Option<clap_complete::Shell>,
+		/// Write the script to where the shell loads completions from (~/.zfunc for zsh) instead of
+		/// printing it.
+		#[arg(long)]
+		install: bool,
+		/// File to install the script to instead.
+		#[arg(long, requires = "install")]
+		path: Option<PathBuf>,
+	},
 	/// Generates a key for a script under the logged in fumosclub account.
 	Generate {
 		#[arg(long)]
@@ -35,12 +170,214 @@ pub enum Command {
 	},
 }
 
+#[derive(Subcommand, Clone, Debug)]
+pub enum ModulesCommand {
+	/// Lists the modules of the current project with their paths and sizes.
+	List {
+		/// Print JSON for tools instead; see `fumosync schema modules-list` for its shape.
+		#[arg(long)]
+		json: bool,
+		/// Also list the modules of the linked remote script.
+		#[arg(long)]
+		remote: bool,
+	},
+	/// Renames a module; the next push (or this one, with --push) sends it under the new name.
+	Rename {
+		old: String,
+		new: String,
+		/// Also change every `requireM("old")` in the project to require the new name.
+		#[arg(long)]
+		rewrite_requires: bool,
+		/// Push the project right after renaming.
+		#[arg(long)]
+		push: bool,
+		/// Only print what renaming (and with --push, pushing) would change, without writing or
+		/// sending anything.
+		#[arg(long)]
+		dry_run: bool,
+	},
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum SchemaKind {
+	/// fumosync.json
+	#[default]
+	Config,
+	/// The output of `modules list --json`.
+	ModulesList,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+	/// Color when writing to a terminal and NO_COLOR isn't set.
+	Auto,
+	Always,
+	Never,
+}
+
+impl ColorChoice {
+	fn enabled(self) -> bool {
+		match self {
+			ColorChoice::Always => true,
+			ColorChoice::Never => false,
+			ColorChoice::Auto => {
+				std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+					&& std::io::stderr().is_terminal()
+			}
+		}
+	}
+}
+
 /// Fumosync allows you to push and pull local projects to fumosclub.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
 	#[command(subcommand)]
 	command: Command,
+	/// Configuration file of the project in the current directory, instead of fumosync.json or
+	/// fumosync.toml; it's read as TOML when it ends in `.toml`.
+	#[arg(long, global = true, env = "FUMOSYNC_CONFIG")]
+	config: Option<PathBuf>,
+	/// Layout used whenever fumosync.json is written.
+	#[arg(long, global = true, value_enum, default_value_t = ConfigFormat::Pretty)]
+	format: ConfigFormat,
+	/// Log more; -v shows debug messages, -vv traces, and -vvv adds per-phase timings.
+	#[arg(short, long, global = true, action = clap::ArgAction::Count)]
+	verbose: u8,
+	/// Fail with exit status 2 instead of warning about problems that don't stop a push, like
+	/// files that can't be read or a missing main source. Retried requests, failed module uploads
+	/// (already an error) and making a script public (confirmed separately) still only warn.
+	#[arg(long, global = true)]
+	strict: bool,
+	/// Log the full HTTP traffic with fumosclub (methods, URLs, headers, bodies and statuses), with
+	/// the session and other credentials redacted.
+	#[arg(long, global = true)]
+	trace_http: bool,
+	/// Print how long each phase of a push or pull (reading the configuration and files,
+	/// validating against the remote, and the network) took once it's done.
+	#[arg(long, global = true)]
+	timings: bool,
+	/// Answer yes to every confirmation and pick everything in every selection instead of asking;
+	/// each answer is still logged.
+	#[arg(
+		short,
+		long,
+		global = true,
+		env = "FUMOSYNC_YES",
+		value_parser = clap::builder::FalseyValueParser::new()
+	)]
+	yes: bool,
+	/// Also write every warning, with its category (network, fidelity, lint, safety or other), to
+	/// this file as JSON once the command is done.
+	#[arg(long, global = true, value_name = "PATH")]
+	warnings_as_json: Option<PathBuf>,
+	/// When to color log output; `always` and `never` override terminal detection and NO_COLOR.
+	#[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+	color: ColorChoice,
+	/// Request timeout in seconds. Precedence: this flag, then FUMOSYNC_TIMEOUT, then `timeout` in
+	/// fumosync.json, then in the user's and the organization's defaults, then 30.
+	#[arg(long, global = true, env = "FUMOSYNC_TIMEOUT")]
+	timeout: Option<u64>,
+	/// Retries per failed request. Precedence: this flag, then FUMOSYNC_RETRIES, then `retries` in
+	/// fumosync.json, then in the user's and the organization's defaults, then 0.
+	#[arg(long, global = true, env = "FUMOSYNC_RETRIES")]
+	retries: Option<u32>,
+	/// File holding the session to use, such as a mounted Docker or Kubernetes secret; trailing
+	/// whitespace is ignored. FUMOSYNC_SESSION wins over it, and it wins over `fumosync login`.
+	#[arg(
+		long,
+		global = true,
+		env = "FUMOSYNC_SESSION_FILE",
+		value_name = "PATH"
+	)]
+	session_file: Option<PathBuf>,
+	/// Root of the fumosclub API. Precedence: this flag, then FUMOSYNC_BASE_URL, then `baseUrl` in
+	/// fumosync.json, then in the user's and the organization's defaults, then
+	/// https://fumosclubv1.vercel.app.
+	#[arg(long, global = true, env = "FUMOSYNC_BASE_URL")]
+	base_url: Option<String>,
+}
+
+/// Loads FUMOSYNC_* variables from a .env file in the current directory. Variables already set in
+/// the environment win over the file, so the overall precedence is flag > environment > .env >
+/// fumosync.json > default.
+fn load_dotenv() -> Result<(), dotenvy::Error> {
+	let entries = match dotenvy::from_path_iter(".env") {
+		Ok(entries) => entries,
+		Err(e) if e.not_found() => return Ok(()),
+		Err(e) => return Err(e),
+	};
+
+	for entry in entries {
+		let (key, value) = entry?;
+		if key.starts_with("FUMOSYNC_") && std::env::var_os(&key).is_none() {
+			std::env::set_var(key, value);
+		}
+	}
+
+	Ok(())
+}
+
+/// Resolves network settings; flags and their environment variables come first, then the
+/// project's fumosync.json (if there is one), then the user's and organization's defaults, then
+/// the built in defaults.
+async fn client_options(args: &Args) -> ClientOptions {
+	let configuration = read_configuration(Path::new(".")).await.ok();
+	let shared = shared_defaults();
+	let defaults = ClientOptions::default();
+
+	let options = ClientOptions {
+		base_url: args
+			.base_url
+			.as_deref()
+			.or(configuration.as_ref().and_then(|c| c.base_url.as_deref()))
+			.or(shared.base_url.as_deref())
+			.unwrap_or(client::BASE_URL)
+			.trim_end_matches('/')
+			.to_owned(),
+		timeout: args
+			.timeout
+			.or(configuration.as_ref().and_then(|c| c.timeout))
+			.or(shared.timeout)
+			.map(Duration::from_secs)
+			.unwrap_or(defaults.timeout),
+		retries: args
+			.retries
+			.or(configuration.as_ref().and_then(|c| c.retries))
+			.or(shared.retries)
+			.unwrap_or(defaults.retries),
+		trace_http: args.trace_http,
+		backoff: match configuration.as_ref().and_then(|c| c.backoff.as_ref()) {
+			Some(settings) => Backoff::new(
+				settings
+					.base_delay_ms
+					.map(Duration::from_millis)
+					.unwrap_or(Backoff::DEFAULT_BASE_DELAY),
+				settings.multiplier.unwrap_or(Backoff::DEFAULT_MULTIPLIER),
+				settings
+					.max_delay_ms
+					.map(Duration::from_millis)
+					.unwrap_or(Backoff::DEFAULT_MAX_DELAY),
+			),
+			None => defaults.backoff,
+		},
+	};
+
+	debug!("effective network settings: {options:?}");
+	if let Some(configuration) = &configuration {
+		debug!(
+			"effective groups file: {:?}, formatter: {:?}",
+			configuration
+				.groups_file
+				.as_ref()
+				.or(shared.groups_file.as_ref()),
+			configuration
+				.formatter
+				.as_ref()
+				.or(shared.formatter.as_ref())
+		);
+	}
+	options
 }
 
 async fn ensure_config_directory_exists() {
@@ -54,40 +391,130 @@ async fn ensure_config_directory_exists() {
 	}
 }
 
-#[tokio::main]
-async fn main() {
-	match main_fn().await {
-		Err(e) => {
-			tracing::error!("{e}")
+fn main() {
+	// before the runtime starts its threads, since setting variables is only sound while nothing
+	// else may be reading the environment
+	let dotenv = load_dotenv();
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.build()
+		.expect("the tokio runtime to start");
+	runtime.block_on(async {
+		let result = main_fn(dotenv).await;
+		if let Err(e) = report_warnings().await {
+			tracing::error!("failed reporting warnings: {e}");
 		}
-		Ok(_t) => {}
-	}
+		match result {
+			Err(e) => {
+				tracing::error!("{e}");
+				std::process::exit(e.exit_code());
+			}
+			Ok(_t) => {}
+		}
+	});
 }
 
-async fn main_fn() -> Result<(), Error> {
+/// Runs the command given on the command line; `dotenv` is the result of loading .env, which
+/// happened before parsing so that flags reading their defaults from the environment see it too.
+async fn main_fn(dotenv: Result<(), dotenvy::Error>) -> Result<(), Error> {
+	let args = Args::parse();
+	let timings = Timings::default();
+	let warnings = Warnings::default();
+	let level = match args.verbose {
+		0 => Level::INFO,
+		1 => Level::DEBUG,
+		_ => Level::TRACE,
+	};
+	warnings.install(args.warnings_as_json.clone());
+	// logs go to stderr, so stdout only ever holds what a command prints, like JSON
 	tracing_subscriber::fmt()
+		.with_writer(std::io::stderr)
 		.compact()
 		.with_target(false)
 		.without_time()
 		.with_level(true)
+		.with_ansi(args.color.enabled())
+		.with_max_level(match args.trace_http {
+			true => Level::TRACE,
+			false => level,
+		})
+		// -vvv also reports how long each push/pull phase took
+		.with_span_events(match args.verbose {
+			0..=2 => FmtSpan::NONE,
+			_ => FmtSpan::CLOSE,
+		})
+		.finish()
+		.with(args.timings.then(|| timings.clone()))
+		.with(warnings)
+		// only the traffic itself, not the trace logs of every other crate
+		.with(args.trace_http.then(|| {
+			Targets::new()
+				.with_default(level)
+				.with_target("http", Level::TRACE)
+		}))
 		.init();
-	warn!("fumosync is beta software; please report bugs to https://github.com/techs-sus/fumosync");
-	let args = Args::parse();
-	ensure_config_directory_exists().await;
+	if let Err(e) = dotenv {
+		warn!("ignoring .env: {e}");
+	}
+	// a no-op push in quiet mode should produce no output at all, and JSON output is meant to be
+	// read by other programs
+	let quiet = match &args.command {
+		Command::Push(options) => options.quiet_unchanged,
+		Command::Schema { .. } => true,
+		Command::Completions { install, .. } => !install,
+		Command::Modules {
+			command: ModulesCommand::List { json, .. },
+		} => *json,
+		_ => false,
+	};
+	if !quiet {
+		warn!(target: "notice", "fumosync is beta software; please report bugs to https://github.com/techs-sus/fumosync");
+	}
+	// doctor only ever looks
+	if !matches!(args.command, Command::Doctor) {
+		ensure_config_directory_exists().await;
+	}
+	if let Some(config) = args.config.clone() {
+		override_configuration_path(config);
+	}
+	if args.yes {
+		prompt::assume_yes();
+	}
+	if let Some(session_file) = args.session_file.clone() {
+		use_session_file(session_file);
+	}
+	interrupt::listen();
+	Defaults::read().await.install();
+	let client_options = client_options(&args).await;
 
 	match args.command {
 		Command::View => {
-			let client = Client::new(get_session_secrets().await?);
+			let client = Client::from_saved_session(client_options).await?;
 			let details = client.get_details().await?;
 			println!(
 				"{} - {} - {}\n{} currently logged in sessions",
 				details.name, details.roblox_user, details.id, details.num_sessions
 			)
 		}
-		Command::Init { project_directory } => init(project_directory).await?,
-		Command::Login => save_session_secrets(use_browser_token()).await?,
+		Command::Init {
+			project_directory,
+			repair: true,
+			..
+		} => repair(project_directory, args.format).await?,
+		Command::Init {
+			project_directory,
+			name,
+			git,
+			repair: false,
+		} => {
+			init(project_directory.clone(), name.as_deref(), args.format).await?;
+			if git {
+				init_git(&project_directory).await?;
+			}
+		}
+		Command::Login => save_session_secrets(use_browser_token(&client_options)?).await?,
 		Command::List => {
-			let client = Client::new(get_session_secrets().await?);
+			let client = Client::from_saved_session(client_options).await?;
 			for script in client.list_scripts().await?.scripts {
 				println!(
 					"{} {} ({}) by {} {}",
@@ -108,21 +535,165 @@ async fn main_fn() -> Result<(), Error> {
 		Command::Pull {
 			script_id,
 			project_directory,
+			name,
+			options,
 		} => {
-			pull_project(script_id, project_directory).await?;
+			let client = Client::from_saved_session(client_options).await?;
+			let options = PullOptions { name, ..options };
+			pull_project(script_id, project_directory, args.format, &options, &client).await?;
 		}
+		Command::PullModule { name } => {
+			let client = Client::from_saved_session(client_options).await?;
+			pull_module(&name, &client).await?
+		}
+		Command::PullAll { jobs, options } => {
+			let client = Client::from_saved_session(client_options).await?;
+			pull_all(jobs, args.format, &options, &client).await?
+		}
+		Command::Push(options) => {
+			let client = Client::from_saved_session(client_options).await?;
+			let options = PushOptions {
+				diagnostics: Diagnostics {
+					strict: args.strict,
+				},
+				..options
+			};
+			let report = push_project(&options, &client).await?;
+			if !options.quiet_unchanged || report.updates > 0 {
+				info!("{}", report.summary());
+			}
+		}
+		Command::Sync { prefer } => {
+			let client = Client::from_saved_session(client_options).await?;
+			let diagnostics = Diagnostics {
+				strict: args.strict,
+			};
+			sync(prefer, args.format, diagnostics, &client).await?
+		}
+		Command::Status => {
+			let client = Client::from_saved_session(client_options).await?;
+			status(&client).await?
+		}
+		Command::Fork {
+			script_id,
+			project_directory,
+			force,
+			confirm_publicity,
+		} => {
+			let client = Client::from_saved_session(client_options).await?;
+			let options = PushOptions {
+				confirm_publicity,
+				diagnostics: Diagnostics {
+					strict: args.strict,
+				},
+				..PushOptions::default()
+			};
+			fork_project(
+				script_id,
+				project_directory,
+				force,
+				args.format,
+				&options,
+				&client,
+			)
+			.await?
+		}
+		Command::Diff {
+			stat,
+			git_ref,
+			compare_with: None,
+		} => {
+			let client = Client::from_saved_session(client_options).await?;
+			diff_project(stat, git_ref.as_deref(), &client).await?
+		}
+		Command::Diff {
+			stat,
+			compare_with: Some(other),
+			..
+		} => {
+			let client = Client::from_saved_session(client_options).await?;
+			let script_id = read_configuration(Path::new(".")).await?.script_id;
+			compare_scripts(&script_id, &other, stat, &client).await?
+		}
+		Command::Modules {
+			command: ModulesCommand::List { json, remote },
+		} => match remote {
+			true => {
+				let client = Client::from_saved_session(client_options).await?;
+				list_modules(json, Some(&client)).await?
+			}
+			false => list_modules(json, None).await?,
+		},
+		Command::Modules {
+			command:
+				ModulesCommand::Rename {
+					old,
+					new,
+					rewrite_requires,
+					push,
+					dry_run,
+				},
+		} => {
+			let path = rename_module(&old, &new, rewrite_requires, dry_run, args.format).await?;
+			if push && dry_run {
+				println!("would update module {new} ({})", path.display());
+			} else if push {
+				let client = Client::from_saved_session(client_options).await?;
+				let options = PushOptions {
+					diagnostics: Diagnostics {
+						strict: args.strict,
+					},
+					..PushOptions::default()
+				};
+				push_project(&options, &client).await?;
+			}
+		}
+		Command::Unlink => unlink_project(args.format).await?,
+		Command::Doctor => doctor(client_options).await?,
+		Command::VerifyRoundtrip { script_id } => {
+			let client = Client::from_saved_session(client_options).await?;
+			verify_roundtrip(script_id, &client).await?
+		}
+		Command::DumpEditor { script_id, raw } => {
+			let client = Client::from_saved_session(client_options).await?;
+			let script_id = match script_id {
+				Some(script_id) => script_id,
+				None => read_configuration(Path::new(".")).await?.script_id,
+			};
 
-		Command::Push => push_project().await?,
+			let body = client.get_editor_raw(&script_id).await?;
+			let body = match raw {
+				true => body,
+				false => serde_json::to_string_pretty(&serde_json::from_str::<serde_json::Value>(&body)?)?,
+			};
+			// the server shouldn't echo the session back, but make sure it never ends up in a report
+			match client.secrets.session.is_empty() {
+				true => println!("{body}"),
+				false => println!("{}", body.replace(&client.secrets.session, "<redacted>")),
+			}
+		}
+		Command::Schema { of } => match of {
+			SchemaKind::Config => println!("{}", configuration_schema()?),
+			SchemaKind::ModulesList => println!("{}", listing_schema()?),
+		},
+		Command::Completions {
+			shell,
+			install,
+			path,
+		} => completions(shell, install, path, Args::command()).await?,
 		Command::Generate { id } => {
-			let client = Client::new(get_session_secrets().await?);
+			let client = Client::from_saved_session(client_options).await?;
 			let id = match id {
 				Some(id) => id,
-				None => read_configuration().await?.script_id,
+				None => read_configuration(Path::new(".")).await?.script_id,
 			};
 
 			println!("{}", client.generate_key(&id).await?);
 		}
 	}
 
+	if args.timings {
+		timings.print();
+	}
 	Ok(())
 }
@@ -0,0 +1,286 @@
+use crate::{
+	client::{Client, EditorUpdate},
+	error::{Context, Error, PathContext},
+	project::{
+		configuration_path, obstruction, read_configuration, read_file, rename_requires,
+		write_configuration, write_file, ConfigFormat, LocalProject, PushOptions,
+	},
+	state::{hash, State},
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Renames the module `old` of the project in the current directory to `new`. Its file is moved
+/// within the directory it's in, unless the module is named by `aliases` or `files.modules`, in
+/// which case only the configuration changes. With `rewrite_requires`, every `requireM` of `old`
+/// is changed to require `new`. With `dry_run`, only prints what would change. Returns where the
+/// module's file is (or would be) afterwards.
+pub async fn rename_module(
+	old: &str,
+	new: &str,
+	rewrite_requires: bool,
+	dry_run: bool,
+	format: ConfigFormat,
+) -> Result<PathBuf, Error> {
+	let root = Path::new(".");
+	let mut project = LocalProject::read(root, &PushOptions::default()).await?;
+	let path = project
+		.modules
+		.iter()
+		.find(|module| module.name == old)
+		.with_context(|| Error::UnknownModule(old.to_owned()))?
+		.path
+		.clone();
+	if let Some(other) = project.modules.iter().find(|module| module.name == new) {
+		return Err(Error::ModuleAlreadyExists(
+			new.to_owned(),
+			other.path.clone(),
+		));
+	}
+
+	let configuration = &mut project.configuration;
+	let mapped = match &mut configuration.files {
+		Some(files) => match files.modules.remove(old) {
+			Some(mapped_path) => {
+				files.modules.insert(new.to_owned(), mapped_path);
+				true
+			}
+			None => false,
+		},
+		None => match configuration
+			.aliases
+			.iter_mut()
+			.find(|(alias_path, _)| root.join(alias_path) == path)
+		{
+			Some((_, name)) => {
+				*name = new.to_owned();
+				true
+			}
+			None => false,
+		},
+	};
+
+	let renamed_path = match mapped {
+		true if dry_run => {
+			println!(
+				"would rename module {old} to {new} in {}",
+				configuration_path(root).display()
+			);
+			path.clone()
+		}
+		true => {
+			write_configuration(configuration_path(root), configuration, format).await?;
+			info!(
+				"renamed module {old} to {new} in {}",
+				configuration_path(root).display()
+			);
+			path.clone()
+		}
+		false => {
+			// whatever follows the name, like `.luau` or `.module.luau`
+			let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+			let suffix = file_name.strip_prefix(old).unwrap_or(".luau");
+			let destination = path.with_file_name(format!("{new}{suffix}"));
+			if tokio::fs::try_exists(&destination).await? {
+				return Err(Error::ModuleAlreadyExists(new.to_owned(), destination));
+			}
+			match dry_run {
+				true => println!("would move {} to {}", path.display(), destination.display()),
+				false => {
+					tokio::fs::rename(&path, &destination)
+						.await
+						.with_path(Error::RenameFile, &path)?;
+					info!("moved {} to {}", path.display(), destination.display());
+				}
+			}
+			destination
+		}
+	};
+
+	if rewrite_requires {
+		let sources =
+			std::iter::once(project.main_path.clone()).chain(project.modules.iter().map(|module| {
+				match module.path == path {
+					true => renamed_path.clone(),
+					false => module.path.clone(),
+				}
+			}));
+		for source_path in sources {
+			// still at the old path during a dry run
+			let source = match dry_run && source_path == renamed_path {
+				true => read_file(&path).await?,
+				false => read_file(&source_path).await?,
+			};
+			let Some(renamed) = rename_requires(&source, old, new) else {
+				continue;
+			};
+			match dry_run {
+				true => println!(
+					"would rewrite requires of {old} in {}",
+					source_path.display()
+				),
+				false => {
+					write_file(&source_path, &renamed).await?;
+					info!("rewrote requires of {old} in {}", source_path.display());
+				}
+			}
+		}
+	}
+	if dry_run {
+		return Ok(renamed_path);
+	}
+
+	// keep the module where the remote listed it, so pushes don't reorder the modules
+	let mut state = State::read(root).await?;
+	if let Some(name) = state.module_order.iter_mut().find(|name| *name == old) {
+		*name = new.to_owned();
+		state.write(root).await?;
+	}
+
+	warn!(target: "fidelity", "fumosclub can't delete modules; pushing adds {new}, but {old} stays on the remote script until it's removed there");
+	Ok(renamed_path)
+}
+
+/// Writes the remote source of the module `name` into the project in the current directory,
+/// replacing whatever its file holds and leaving every other file alone. fumosclub only hands out
+/// whole scripts, so the entire editor is still fetched.
+pub async fn pull_module(name: &str, client: &Client) -> Result<(), Error> {
+	let root = Path::new(".");
+	let configuration = read_configuration(root).await?;
+	let script_id = &configuration.script_id;
+	let mut source = client
+		.get_editor(script_id)
+		.await?
+		.script_info
+		.source
+		.modules
+		.shift_remove(name)
+		.with_context(|| Error::UnknownRemoteModule(name.to_owned(), script_id.clone()))?;
+	if let Some(policy) = configuration.trailing_newline {
+		policy.apply(&mut source);
+	}
+
+	let update = EditorUpdate::Module {
+		name,
+		source: &source,
+	};
+	let path = configuration.module_path(root, name);
+	if let Some(obstruction) = obstruction(root, &path).await? {
+		return Err(Error::PathConflict(update.label(), path, obstruction));
+	}
+	if let Some(directory) = path.parent() {
+		tokio::fs::create_dir_all(directory)
+			.await
+			.with_path(Error::CreateDirectory, directory)?;
+	}
+	write_file(&path, &source).await?;
+	info!("wrote module {name} to {}", path.display());
+
+	let mut state = State::read(root).await?;
+	state.mark_synced(&update);
+	state.write(root).await
+}
+
+/// What `modules list --json` prints. Fields are only ever added, so tools can rely on the
+/// existing ones; `fumosync schema modules-list` prints the JSON Schema.
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleListing {
+	/// Modules of the project, in the order they're pushed in.
+	pub local: Vec<ModuleEntry>,
+	/// Modules of the linked remote script, in the order the server lists them; only present
+	/// with `--remote`.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub remote: Option<Vec<ModuleEntry>>,
+}
+
+#[derive(Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleEntry {
+	pub name: String,
+	/// Path of the module's file relative to the project root; remote modules have none.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub path: Option<PathBuf>,
+	/// Size of the source in bytes, as pushed.
+	pub bytes: usize,
+	/// Hex encoded SHA-256 of the source, as pushed.
+	pub hash: String,
+}
+
+/// Lists the modules of the project in the current directory and, with `remote`, of its linked
+/// script, as a table or with `json` as a `ModuleListing`.
+pub async fn list_modules(json: bool, remote: Option<&Client>) -> Result<(), Error> {
+	let root = Path::new(".");
+	let mut project = LocalProject::read(root, &PushOptions::default()).await?;
+	project.sort_modules(&State::read(root).await?.module_order);
+
+	let listing = ModuleListing {
+		local: project
+			.modules
+			.iter()
+			.map(|module| ModuleEntry {
+				name: module.name.clone(),
+				path: Some(
+					module
+						.path
+						.strip_prefix(root)
+						.unwrap_or(&module.path)
+						.to_path_buf(),
+				),
+				bytes: module.source.len(),
+				hash: hash(&module.source),
+			})
+			.collect(),
+		remote: match remote {
+			Some(client) => Some(
+				client
+					.get_editor(&project.configuration.script_id)
+					.await?
+					.script_info
+					.source
+					.modules
+					.iter()
+					.map(|(name, source)| ModuleEntry {
+						name: name.clone(),
+						path: None,
+						bytes: source.len(),
+						hash: hash(source),
+					})
+					.collect(),
+			),
+			None => None,
+		},
+	};
+
+	if json {
+		println!("{}", serde_json::to_string_pretty(&listing)?);
+		return Ok(());
+	}
+
+	let sets = std::iter::once(("local", &listing.local))
+		.chain(listing.remote.iter().map(|modules| ("remote", modules)));
+	for (side, modules) in sets {
+		println!("{side}:");
+		for module in modules {
+			match &module.path {
+				Some(path) => println!(
+					"  {} ({}, {} bytes)",
+					module.name,
+					path.display(),
+					module.bytes
+				),
+				None => println!("  {} ({} bytes)", module.name, module.bytes),
+			}
+		}
+	}
+	Ok(())
+}
+
+/// JSON Schema describing what `modules list --json` prints.
+pub fn listing_schema() -> Result<String, Error> {
+	Ok(serde_json::to_string_pretty(&schemars::schema_for!(
+		ModuleListing
+	))?)
+}
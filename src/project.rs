@@ -1,76 +1,746 @@
 use crate::{
-	client::{Client, EditorUpdate},
-	error::Error,
-	login::get_session_secrets,
+	client::{Client, EditorScriptInfo, EditorUpdate},
+	defaults::shared_defaults,
+	diagnostics::{Category, Diagnostics},
+	diff::changed_since,
+	error::{Context, Error, PathContext},
+	interrupt::{finish_despite_interrupt, interrupted},
+	prompt,
+	state::{field_key, fingerprint, hash, HashCache, PushRecord, State, STATE_DIRECTORY},
 };
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use serde_json::ser::PrettyFormatter;
 use std::{
+	cmp::Ordering,
+	collections::{BTreeMap, HashMap, HashSet},
 	ffi::OsStr,
-	path::{Path, PathBuf},
+	future::Future,
+	io::{self, IsTerminal},
+	ops::Range,
+	path::{Component, Path, PathBuf},
+	sync::OnceLock,
+	time::{Duration, Instant},
 };
+use tokio::io::AsyncReadExt;
+use tokio_util::sync::CancellationToken;
+use tracing::{field, info, info_span, warn, Instrument};
 
-/// fumosync.json
-#[derive(Deserialize, Serialize)]
+/// `script_id` of a project that isn't linked to a remote script.
+pub const PLACEHOLDER_SCRIPT_ID: &str = "???";
+
+/// fumosync.json, or fumosync.toml
+#[derive(Deserialize, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Configuration {
+	/// Where editors find the schema of this file; kept as is, not used by fumosync.
+	#[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+	pub schema: Option<String>,
 	pub script_name: String,
 	pub script_id: String,
 	pub whitelist: Vec<String>,
 	pub is_public: bool,
+	/// Root of the fumosclub API, used unless `--base-url` or FUMOSYNC_BASE_URL is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub base_url: Option<String>,
+	/// Request timeout in seconds, used unless `--timeout` or `FUMOSYNC_TIMEOUT` is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timeout: Option<u64>,
+	/// Retries per request, used unless `--retries` or `FUMOSYNC_RETRIES` is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub retries: Option<u32>,
+	/// File mapping group names to member ids, for `@group` whitelist entries; defaults to the
+	/// user's or organization's `groupsFile`, then fumosync.groups.json.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub groups_file: Option<PathBuf>,
+	/// Whether the main source and modules end with a newline, both when pull writes them and when
+	/// push reads them; left as they are when unset.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub trailing_newline: Option<TrailingNewline>,
+	/// Command (and leading arguments) run from the project root with the path of every `.luau`
+	/// file a pull writes, such as `["stylua"]`; defaults to the user's or organization's.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub formatter: Option<Vec<String>>,
+	/// File holding the script's description; defaults to README.md.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub description_file: Option<PathBuf>,
+	/// Delays between retries.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub backoff: Option<BackoffSettings>,
+	/// Limits on a single upload request; bigger pushes are split into several requests. Ignored
+	/// when `moduleConcurrency` is set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub batch: Option<BatchLimits>,
+	/// Upload modules with one request each, this many at a time, instead of in a single batch;
+	/// wins over `batch`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub module_concurrency: Option<usize>,
+	/// Directories scanned for modules, in order; defaults to pkg. Pull writes modules into the
+	/// first one.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub module_directories: Vec<PathBuf>,
+	/// File extensions (without the leading dot) of the files read as modules; defaults to luau.
+	/// Whatever the extension, a module's file is pushed as its source text.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub module_extensions: Vec<String>,
+	/// What happens when two module directories hold a module of the same name.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub module_collisions: Option<ModuleCollisions>,
+	/// Module file path -> the name it's pushed (and required) as, instead of its file name. Paths
+	/// may point anywhere in the project, such as into a subdirectory of pkg/.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub aliases: BTreeMap<PathBuf, String>,
+	/// Limits a push has to stay within, for catching runaway growth in automation.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub budget: Option<Budget>,
+	/// Explicit list of the files to push; replaces the pkg/ scan when present.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub files: Option<Manifest>,
+	/// Named audiences, like `dev` and `prod`, whose whitelist and publicity `push --env` sends
+	/// instead of the ones above. Pulls always write to the ones above.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub environments: BTreeMap<String, Environment>,
 }
 
-pub async fn write_file<T: AsRef<Path>>(path: T, contents: &str) -> Result<(), Error> {
-	match tokio::fs::write(path.as_ref(), contents).await {
-		Ok(value) => Ok(value),
-		Err(io_error) => Err(Error::CreateFile(path.as_ref().to_path_buf(), io_error)),
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Environment {
+	/// Replaces `whitelist`; may use `@group` entries.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub whitelist: Option<Vec<String>>,
+	/// Replaces `isPublic`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub is_public: Option<bool>,
+}
+
+/// An unlinked, private script with an empty whitelist and every file at its default path; what
+/// `init` writes, besides the name.
+impl Default for Configuration {
+	fn default() -> Self {
+		Self {
+			schema: None,
+			script_name: "unknown".to_owned(),
+			script_id: PLACEHOLDER_SCRIPT_ID.to_owned(),
+			whitelist: Vec::new(),
+			is_public: false,
+			base_url: None,
+			timeout: None,
+			retries: None,
+			groups_file: None,
+			trailing_newline: None,
+			formatter: None,
+			description_file: None,
+			backoff: None,
+			batch: None,
+			module_concurrency: None,
+			module_directories: Vec::new(),
+			module_extensions: Vec::new(),
+			module_collisions: None,
+			aliases: BTreeMap::new(),
+			budget: None,
+			files: None,
+			environments: BTreeMap::new(),
+		}
 	}
 }
 
-async fn create_directory<T: AsRef<Path>>(path: T) -> Result<(), Error> {
-	match tokio::fs::create_dir(path.as_ref()).await {
-		Ok(value) => Ok(value),
-		Err(io_error) => Err(Error::CreateDirectory(
-			path.as_ref().to_path_buf(),
-			io_error,
-		)),
+/// Builds a `Configuration` from the defaults, like
+/// `Configuration::builder().script_name("x").public(true).build()`.
+#[derive(Default)]
+pub struct ConfigurationBuilder {
+	configuration: Configuration,
+}
+
+impl ConfigurationBuilder {
+	pub fn script_name(mut self, script_name: impl Into<String>) -> Self {
+		self.configuration.script_name = script_name.into();
+		self
+	}
+
+	pub fn script_id(mut self, script_id: impl Into<String>) -> Self {
+		self.configuration.script_id = script_id.into();
+		self
+	}
+
+	pub fn whitelist(mut self, whitelist: Vec<String>) -> Self {
+		self.configuration.whitelist = whitelist;
+		self
+	}
+
+	pub fn public(mut self, is_public: bool) -> Self {
+		self.configuration.is_public = is_public;
+		self
+	}
+
+	pub fn build(self) -> Configuration {
+		self.configuration
+	}
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackoffSettings {
+	/// Wait before the first retry, in milliseconds; defaults to 500.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub base_delay_ms: Option<u64>,
+	/// How many times longer each following wait is; defaults to 2.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub multiplier: Option<f64>,
+	/// Longest wait, in milliseconds; defaults to 32000.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_delay_ms: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchLimits {
+	/// Most updates (fields and modules) sent in one request.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_updates: Option<usize>,
+	/// Most bytes of source text sent in one request.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_bytes: Option<usize>,
+}
+
+impl BatchLimits {
+	/// Splits `updates` into consecutive batches within the limits, keeping their order. All
+	/// non-module updates go into the first batch whatever the limits, and a single update over
+	/// the byte limit still gets a batch of its own.
+	pub fn split<'a, 'b>(&self, updates: &'b [EditorUpdate<'a>]) -> Vec<&'b [EditorUpdate<'a>]> {
+		let size = |update: &EditorUpdate| match update {
+			EditorUpdate::Whitelist(whitelist) => whitelist.iter().map(|id| id.len()).sum(),
+			update => update.text().map_or(0, str::len),
+		};
+
+		let mut batches = Vec::new();
+		let (mut start, mut bytes) = (0, 0);
+		for (index, update) in updates.iter().enumerate() {
+			let full = self.max_updates.is_some_and(|max| index - start >= max)
+				|| self.max_bytes.is_some_and(|max| bytes + size(update) > max);
+			if full && index > start && matches!(update, EditorUpdate::Module { .. }) {
+				batches.push(&updates[start..index]);
+				(start, bytes) = (index, 0);
+			}
+			bytes += size(update);
+		}
+		if start < updates.len() {
+			batches.push(&updates[start..]);
+		}
+		batches
+	}
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Budget {
+	/// Largest allowed total size of the main source and modules, in kilobytes.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_size_kb: Option<u64>,
+	/// Longest the upload may take, in seconds.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_seconds: Option<u64>,
+	/// Most modules a push may send; defaults to 1000.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub max_modules: Option<usize>,
+}
+
+/// How many modules a push accepts unless `--max-modules` or `budget.maxModules` says otherwise;
+/// far more than a hand written project has, so hitting it means a scan picked up the wrong files.
+const DEFAULT_MAX_MODULES: usize = 1000;
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum TrailingNewline {
+	/// End every source with exactly one newline.
+	Ensure,
+	/// Remove newlines from the end of every source.
+	Strip,
+}
+
+impl TrailingNewline {
+	pub fn apply(self, source: &mut String) {
+		let trimmed = source.trim_end_matches(['\r', '\n']).len();
+		source.truncate(trimmed);
+		if let TrailingNewline::Ensure = self {
+			source.push('\n');
+		}
+	}
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ModuleCollisions {
+	/// The module from the later directory wins.
+	#[default]
+	Shadow,
+	/// Fail with `ModuleNameCollision`.
+	Error,
+}
+
+impl Configuration {
+	pub fn builder() -> ConfigurationBuilder {
+		ConfigurationBuilder::default()
+	}
+
+	/// Makes the whitelist and publicity those of the environment `name`, where it sets them.
+	pub fn apply_environment(&mut self, name: &str) -> Result<(), Error> {
+		let environment = self.environments.get(name).cloned().with_context(|| {
+			Error::UnknownEnvironment(name.to_owned(), self.environments.keys().cloned().collect())
+		})?;
+		if let Some(whitelist) = environment.whitelist {
+			self.whitelist = whitelist;
+		}
+		if let Some(is_public) = environment.is_public {
+			self.is_public = is_public;
+		}
+		Ok(())
+	}
+
+	/// The directories modules are read from, in order.
+	pub fn module_directories(&self) -> Vec<&Path> {
+		match self.module_directories.is_empty() {
+			true => vec![Path::new("pkg")],
+			false => self
+				.module_directories
+				.iter()
+				.map(PathBuf::as_path)
+				.collect(),
+		}
+	}
+
+	/// The extensions of files read as modules.
+	pub fn module_extensions(&self) -> Vec<&str> {
+		match self.module_extensions.is_empty() {
+			true => vec!["luau"],
+			false => self.module_extensions.iter().map(String::as_str).collect(),
+		}
+	}
+
+	/// Where the main source of the project at `root` lives.
+	pub fn main_path(&self, root: &Path) -> PathBuf {
+		root.join(
+			self
+				.files
+				.as_ref()
+				.and_then(|files| files.main.as_deref())
+				.unwrap_or(Path::new("init.server.luau")),
+		)
+	}
+
+	/// Where the groups file that `@group` whitelist entries expand from lives in the project at
+	/// `root`.
+	pub fn groups_path(&self, root: &Path) -> PathBuf {
+		root.join(
+			self
+				.groups_file
+				.as_deref()
+				.or(shared_defaults().groups_file.as_deref())
+				.unwrap_or(Path::new("fumosync.groups.json")),
+		)
+	}
+
+	/// Where the description of the project at `root` lives.
+	pub fn description_path(&self, root: &Path) -> PathBuf {
+		root.join(
+			self
+				.description_file
+				.as_deref()
+				.unwrap_or(Path::new("README.md")),
+		)
+	}
+
+	/// Where the module `name` lives in the project at `root`: its alias, else its existing file in
+	/// any module directory (including `name.module.luau`), else a new file in the first one.
+	pub fn module_path(&self, root: &Path, name: &str) -> PathBuf {
+		if let Some((path, _)) = self.aliases.iter().find(|(_, alias)| *alias == name) {
+			return root.join(path);
+		}
+
+		let extensions = self.module_extensions();
+		let existing = self.module_directories().into_iter().find_map(|directory| {
+			extensions.iter().find_map(|extension| {
+				[
+					format!("{name}.module.{extension}"),
+					format!("{name}.{extension}"),
+				]
+				.into_iter()
+				.map(|file_name| root.join(directory).join(file_name))
+				.find(|path| path.is_file())
+			})
+		});
+		existing.unwrap_or_else(|| {
+			root
+				.join(self.module_directories()[0])
+				.join(format!("{name}.{}", extensions[0]))
+		})
+	}
+}
+
+/// Maps project files to what they are pushed as, for projects whose layout is generated.
+#[derive(Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+	/// Path to the main source; defaults to init.server.luau.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub main: Option<PathBuf>,
+	/// Module name -> path of its source.
+	#[serde(default)]
+	pub modules: BTreeMap<String, PathBuf>,
+}
+
+/// Flags controlling how `push_project` behaves.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct PushOptions {
+	/// Only send fields and modules that differ from the remote script, printing nothing when
+	/// everything is already up to date.
+	#[arg(long)]
+	pub quiet_unchanged: bool,
+	/// Push an empty main source instead of failing when the main source file is missing, or
+	/// skipping it when the file is blank.
+	#[arg(long)]
+	pub allow_empty_main: bool,
+	/// Push a blank description or main source, which are otherwise skipped so that an empty
+	/// local file doesn't wipe the remote one.
+	#[arg(long)]
+	pub push_empty: bool,
+	/// Also push modules in pkg/ whose file names start with a `.`, which are skipped by default.
+	#[arg(long)]
+	pub include_hidden: bool,
+	/// Push the whitelist and publicity of this entry of `environments` in fumosync.json; sources
+	/// stay the same.
+	#[arg(long, value_name = "NAME", env = "FUMOSYNC_ENV")]
+	pub env: Option<String>,
+	/// Leave the remote description untouched.
+	#[arg(long)]
+	pub no_description: bool,
+	/// Leave the remote whitelist untouched.
+	#[arg(long)]
+	pub no_whitelist: bool,
+	/// Leave whether the remote script is public untouched.
+	#[arg(long)]
+	pub no_publicity: bool,
+	/// Only send fields and modules whose contents changed since they were last pushed or pulled,
+	/// without asking the server. Hashes are cached in .fumosync/cache.json by path, size and mtime.
+	#[arg(long)]
+	pub incremental: bool,
+	/// Print which whitelist entries the push adds (`+ id`) and removes (`- id`) compared to the
+	/// remote script.
+	#[arg(long)]
+	pub verbose_diff: bool,
+	/// Don't warn about modules nothing requires, or about requires of modules that don't exist.
+	#[arg(long)]
+	pub no_lint: bool,
+	/// Only send what's read from files that changed since this git commit (or other ref), including
+	/// uncommitted and untracked ones; name, whitelist and publicity count as changed with the
+	/// configuration file. Outside of a git repository, this compares hashes like --incremental.
+	#[arg(long, value_name = "REF")]
+	pub since_commit: Option<String>,
+	/// Push the main source with every module embedded into it (see `LocalProject::bundle`) and no
+	/// separate modules. Files on disk stay as they are.
+	#[arg(long, conflicts_with_all = ["incremental", "since_commit", "select"])]
+	pub bundle: bool,
+	/// Pick which of the fields and modules changed since the last sync to push. Needs a
+	/// terminal; use the --no-* flags to narrow a push down in scripts instead.
+	#[arg(long)]
+	pub select: bool,
+	/// Make a private remote script public without asking first.
+	#[arg(long)]
+	pub confirm_publicity: bool,
+	/// List what would be pushed without sending anything.
+	#[arg(long)]
+	pub dry_run: bool,
+	/// Push what's read from stdin as the main source of the linked script, and nothing else. No
+	/// project files besides the configuration are read, and .fumosync/state.json is left alone, so
+	/// the next push or sync treats the remote main source as changed.
+	#[arg(long, conflicts_with_all = ["bundle", "select", "incremental", "since_commit", "quiet_unchanged"])]
+	pub main_stdin: bool,
+	/// Why this push happened. fumosclub has no way to annotate edits, so the note is only kept
+	/// locally, with the time and what got pushed, in the history of .fumosync/state.json.
+	#[arg(long, short)]
+	pub message: Option<String>,
+	/// After pushing, fetch what the server stored and remember any rewriting it did (trimmed
+	/// whitespace, changed line endings, ...) in .fumosync/state.json. Later dry runs and
+	/// --quiet-unchanged then treat a remote value that only differs by that rewriting as unchanged.
+	/// fumosync doesn't assume any particular normalization; it only records what it observes.
+	#[arg(long)]
+	pub record_normalization: bool,
+	/// After pushing, fetch what the server stored and warn about every field or module that isn't
+	/// exactly what was sent (failing under --strict).
+	#[arg(long)]
+	pub verify: bool,
+	/// Push sources starting with a UTF-8 byte order mark as they are, instead of stripping it.
+	#[arg(long)]
+	pub keep_bom: bool,
+	/// Write a JSON summary of the push (what was sent, and whether it succeeded) to this path,
+	/// even when the push fails.
+	#[arg(long, value_name = "PATH")]
+	pub summary: Option<PathBuf>,
+	/// Refuse to push more modules than this. Precedence: this flag, then `budget.maxModules` in
+	/// fumosync.json, then 1000.
+	#[arg(long, value_name = "COUNT")]
+	pub max_modules: Option<usize>,
+	/// Set from the global `--strict` flag.
+	#[arg(skip)]
+	pub diagnostics: Diagnostics,
+	/// Stops the push with `Error::Cancelled` once triggered: while the project is read, while
+	/// waiting on the remote script, and between upload batches. An upload already sent to the
+	/// server is waited for, so the remote isn't left half written.
+	#[arg(skip)]
+	pub cancel: CancellationToken,
+	/// Script to push to instead of the linked one, which leaves .fumosync/state.json alone; what
+	/// `fork` pushes with.
+	#[arg(skip)]
+	pub target: Option<String>,
+}
+
+/// How fumosync.json is laid out when written; fumosync.toml is always written as plain TOML.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum ConfigFormat {
+	/// Indented with two spaces, one field per line.
+	#[default]
+	Pretty,
+	/// A single line without any whitespace.
+	Compact,
+}
+
+pub fn serialize_configuration(
+	configuration: &Configuration,
+	format: ConfigFormat,
+) -> Result<String, Error> {
+	match format {
+		ConfigFormat::Pretty => {
+			// spell out the indent so the output doesn't shift if serde_json ever changes its default
+			let mut buffer = Vec::new();
+			let mut serializer =
+				serde_json::Serializer::with_formatter(&mut buffer, PrettyFormatter::with_indent(b"  "));
+			configuration.serialize(&mut serializer)?;
+			Ok(String::from_utf8(buffer).expect("serde_json to produce valid utf-8"))
+		}
+		ConfigFormat::Compact => Ok(serde_json::to_string(configuration)?),
+	}
+}
+
+/// JSON Schema describing fumosync.json, for editors and CI to validate against.
+pub fn configuration_schema() -> Result<String, Error> {
+	Ok(serde_json::to_string_pretty(&schemars::schema_for!(
+		Configuration
+	))?)
+}
+
+/// Writes a configuration as TOML when `path` ends in `.toml` and as JSON otherwise.
+pub async fn write_configuration<T: AsRef<Path>>(
+	path: T,
+	configuration: &Configuration,
+	format: ConfigFormat,
+) -> Result<(), Error> {
+	let contents = configuration_contents(path.as_ref(), configuration, format)?;
+	write_file(path, &contents).await
+}
+
+fn configuration_contents(
+	path: &Path,
+	configuration: &Configuration,
+	format: ConfigFormat,
+) -> Result<String, Error> {
+	match is_toml(path) {
+		true => Ok(toml::to_string_pretty(configuration)?),
+		false => serialize_configuration(configuration, format),
+	}
+}
+
+fn is_toml(path: &Path) -> bool {
+	path.extension() == Some(OsStr::new("toml"))
+}
+
+/// Configuration file passed with `--config`, used for the project in the current directory.
+static CONFIGURATION_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Makes the project in the current directory read (and write back) its configuration at `path`.
+pub fn override_configuration_path(path: PathBuf) {
+	CONFIGURATION_OVERRIDE
+		.set(path)
+		.expect("the configuration path to be overridden once");
+}
+
+/// Where the configuration of the project at `root` lives: the `--config` path for the current
+/// directory if one was given, otherwise fumosync.json, or fumosync.toml when only that exists.
+pub fn configuration_path(root: &Path) -> PathBuf {
+	if normalize_path(root) == normalize_path(Path::new(".")) {
+		if let Some(path) = CONFIGURATION_OVERRIDE.get() {
+			return path.clone();
+		}
 	}
+
+	let json = root.join("fumosync.json");
+	let toml = root.join("fumosync.toml");
+	match !json.exists() && toml.exists() {
+		true => toml,
+		false => json,
+	}
+}
+
+pub async fn write_file<T: AsRef<Path>>(path: T, contents: &str) -> Result<(), Error> {
+	tokio::fs::write(path.as_ref(), contents)
+		.await
+		.with_path(Error::CreateFile, path)
+}
+
+async fn create_directory<T: AsRef<Path>>(path: T) -> Result<(), Error> {
+	tokio::fs::create_dir(path.as_ref())
+		.await
+		.with_path(Error::CreateDirectory, path)
 }
 
-pub async fn read_configuration() -> Result<Configuration, Error> {
-	Ok(serde_json::from_str(&read_file("fumosync.json").await?)?)
+pub async fn read_configuration(root: &Path) -> Result<Configuration, Error> {
+	let path = configuration_path(root);
+	let contents = match tokio::fs::read_to_string(&path).await {
+		Ok(contents) => contents,
+		Err(io_error) if io_error.kind() == io::ErrorKind::NotFound => {
+			return Err(Error::NotAFumosyncProject(root.to_path_buf()))
+		}
+		Err(io_error) => return Err(Error::ReadFile(path, io_error)),
+	};
+	match is_toml(&path) {
+		true => Ok(toml::from_str(&contents)?),
+		false => Ok(serde_json::from_str(&contents)?),
+	}
 }
 
-/// Initializes a project for syncing within fumosclub.
-pub async fn init(directory: PathBuf) -> Result<(), Error> {
+/// Initializes a project for syncing within fumosclub, named `name` or after its directory.
+pub async fn init(
+	directory: PathBuf,
+	name: Option<&str>,
+	format: ConfigFormat,
+) -> Result<(), Error> {
+	let directory = normalize_path(&directory);
 	if directory.exists() {
 		return Err(Error::DirectoryAlreadyExists(directory));
 	}
 
-	create_directory(directory.clone()).await?;
-	create_directory(directory.join("pkg")).await?;
-	create_directory(directory.join(".vscode")).await?;
+	scaffold(&directory, name, format, &mut Scaffold::default()).await
+}
+
+/// Makes `path` absolute and resolves its `.` and `..` components (without following symlinks),
+/// so that `foo`, `foo/` and `./bar/../foo` all name the directory `foo`.
+pub fn normalize_path(path: &Path) -> PathBuf {
+	let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+	let mut normalized = PathBuf::new();
+	for component in absolute.components() {
+		match component {
+			Component::CurDir => {}
+			Component::ParentDir => {
+				normalized.pop();
+			}
+			component => normalized.push(component),
+		}
+	}
+	normalized
+}
+
+/// Recreates whichever scaffold files and directories of the project at `directory` are
+/// missing, leaving everything that exists untouched, and reports what it did.
+pub async fn repair(directory: PathBuf, format: ConfigFormat) -> Result<(), Error> {
+	let directory = normalize_path(&directory);
+	let mut scaffold_log = Scaffold {
+		repair: true,
+		..Scaffold::default()
+	};
+	scaffold(&directory, None, format, &mut scaffold_log).await?;
+
+	for path in &scaffold_log.created {
+		info!("created {}", path.display());
+	}
+	for path in &scaffold_log.kept {
+		info!("kept {}", path.display());
+	}
+	Ok(())
+}
+
+/// Creates scaffold files and directories, or in `repair` mode only those that are missing.
+#[derive(Default)]
+struct Scaffold {
+	repair: bool,
+	created: Vec<PathBuf>,
+	kept: Vec<PathBuf>,
+}
+
+impl Scaffold {
+	async fn keep(&mut self, path: &Path) -> Result<bool, Error> {
+		let keep = self.repair && tokio::fs::try_exists(path).await?;
+		match keep {
+			true => self.kept.push(path.to_path_buf()),
+			false => self.created.push(path.to_path_buf()),
+		}
+		Ok(keep)
+	}
+
+	async fn directory(&mut self, path: PathBuf) -> Result<(), Error> {
+		match (self.keep(&path).await?, self.repair) {
+			(true, _) => Ok(()),
+			// configured module directories can be nested
+			(false, true) => tokio::fs::create_dir_all(&path)
+				.await
+				.with_path(Error::CreateDirectory, &path),
+			(false, false) => create_directory(path).await,
+		}
+	}
+
+	async fn file(&mut self, path: PathBuf, contents: &str) -> Result<(), Error> {
+		if self.keep(&path).await? {
+			return Ok(());
+		}
+		// configured paths can point into directories that don't exist yet
+		if let Some(parent) = path.parent() {
+			tokio::fs::create_dir_all(parent)
+				.await
+				.with_path(Error::CreateDirectory, parent)?;
+		}
+		write_file(path, contents).await
+	}
+}
+
+async fn scaffold(
+	directory: &Path,
+	name: Option<&str>,
+	format: ConfigFormat,
+	scaffold: &mut Scaffold,
+) -> Result<(), Error> {
+	// a repair recreates the files where the project's configuration puts them
+	let layout = match scaffold.repair {
+		true => read_configuration(directory).await.unwrap_or_default(),
+		false => Configuration::default(),
+	};
+	scaffold.directory(directory.to_path_buf()).await?;
+	scaffold
+		.directory(directory.join(layout.module_directories()[0]))
+		.await?;
+	scaffold.directory(directory.join(".vscode")).await?;
 
-	write_file(
-		directory.join(".vscode").join("settings.json"),
-		r#"{
+	scaffold
+		.file(
+			directory.join(".vscode").join("settings.json"),
+			r#"{
 	"luau-lsp.types.robloxSecurityLevel": "None",
 	"luau-lsp.types.definitionFiles": ["types.d.luau"]
 }"#,
-	)
-	.await?;
+		)
+		.await?;
 
-	write_file(
-		directory.join("init.server.luau"),
+	scaffold.file(
+		layout.main_path(directory),
 		r#"-- you can require packages with requireM("path") where path is a file inside of pkg (no extension)"#,
 	)
 	.await?;
 
-	write_file(directory.join("README.md"), r#"# stuff here"#).await?;
+	scaffold
+		.file(layout.description_path(directory), r#"# stuff here"#)
+		.await?;
 
-	write_file(
-		directory.join("types.d.luau"),
-		r#"declare loadstringEnabled: boolean
+	scaffold
+		.file(
+			directory.join("types.d.luau"),
+			r#"declare loadstringEnabled: boolean
 declare owner: Player
 declare arguments: { any }
 
@@ -90,131 +760,2308 @@ declare LoadAssets: (assetId: number) -> {
   GetArray: () -> { Instance },
   GetDictionary: () -> { [string]: Instance }
 }"#,
-	)
-	.await?;
+		)
+		.await?;
 
-	write_file(
-		directory.join("fumosync.json"),
-		&serde_json::to_string_pretty(&Configuration {
-			script_name: directory
+	let configuration = Configuration::builder()
+		.script_name(match name {
+			Some(name) => name.to_owned(),
+			None => directory
 				.file_name()
 				.unwrap_or(OsStr::new("unknown"))
 				.to_string_lossy()
 				.to_string(),
-			script_id: "???".to_owned(),
-			whitelist: Vec::new(),
-			is_public: false,
-		})?,
-	)
-	.await?;
+		})
+		.build();
+	let path = configuration_path(directory);
+	let contents = configuration_contents(&path, &configuration, format)?;
+	scaffold.file(path, &contents).await?;
 
 	Ok(())
 }
 
-/// Pulls a project from fumosclub and links it via fumosync.json.
-pub async fn pull_project(script_id: String, project_directory: PathBuf) -> Result<(), Error> {
-	let client = Client::new(get_session_secrets().await?);
+/// Flags controlling how `pull_project` behaves.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct PullOptions {
+	/// Leave the local main source untouched.
+	#[arg(long)]
+	pub exclude_main: bool,
+	/// Leave local modules untouched.
+	#[arg(long)]
+	pub exclude_modules: bool,
+	/// Leave the local description file (README.md unless `descriptionFile` says otherwise)
+	/// untouched.
+	#[arg(long)]
+	pub exclude_readme: bool,
+	/// Only write the configuration (name, whitelist, publicity and id), leaving every source and
+	/// the description alone.
+	#[arg(long, conflicts_with_all = ["exclude_main", "exclude_modules", "exclude_readme"])]
+	pub config_only: bool,
+	/// When refreshing, delete local modules (found by scanning the module directories) that the
+	/// remote script doesn't have. Modules still required by a remaining source are kept.
+	#[arg(long)]
+	pub prune: bool,
+	/// Only list what --prune would delete and which requires that would break, changing nothing.
+	#[arg(long, requires = "prune")]
+	pub dry_run: bool,
+	/// After pruning, also remove the directories inside module directories that only held pruned
+	/// modules and are now empty. Module directories themselves are kept.
+	#[arg(long, requires = "prune")]
+	pub prune_empty_dirs: bool,
+	/// When the directory isn't a fumosync project yet, don't scaffold one: only write the
+	/// description, main source and modules into it (creating it if needed), overwriting any
+	/// existing files at their paths, and write no fumosync.json or .fumosync state. Has no
+	/// effect when refreshing an existing project.
+	#[arg(long)]
+	pub no_init_scaffold: bool,
+	/// What to do with files edited locally since the last sync when refreshing a project.
+	#[arg(long, value_enum, default_value_t)]
+	pub on_conflict: ConflictPolicy,
+	/// What to do with remote modules whose names only differ by case (like `Net` and `net`),
+	/// which would share a file on case-insensitive filesystems.
+	#[arg(long, value_enum, default_value_t)]
+	pub case_collisions: CaseCollisions,
+	/// Script name to write instead of the remote one.
+	#[arg(skip)]
+	pub name: Option<String>,
+	/// Stops the pull with `Error::Cancelled` once triggered, as long as it's still fetching the
+	/// script; once files are being written, the pull finishes.
+	#[arg(skip)]
+	pub cancel: CancellationToken,
+}
 
-	// setup initial file structure for hydration
-	match init(project_directory.clone()).await {
-		Ok(_) => {}
-		Err(e) => return Err(Error::ProjectDidntInitialize(Box::new(e))),
-	};
+/// How a refreshing pull treats a file that changed both locally and remotely.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ConflictPolicy {
+	/// Replace the local file with the remote one.
+	#[default]
+	Overwrite,
+	/// Keep the local file.
+	Skip,
+	/// Write both versions into the file between git style conflict markers.
+	Markers,
+}
 
-	let script_info = client.get_editor(&script_id).await?.script_info;
+/// How a pull writes remote modules whose names only differ by case.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum CaseCollisions {
+	/// Refuse to pull.
+	#[default]
+	Error,
+	/// Write every module after the first to `name_2.luau` (or `_3` and so on), aliased to its
+	/// real name in the configuration.
+	Suffix,
+}
 
-	write_file(
-		project_directory.join("README.md"),
-		&script_info.description,
-	)
-	.await?;
+impl PullOptions {
+	fn skips_main(&self) -> bool {
+		self.exclude_main || self.config_only
+	}
+
+	fn skips_modules(&self) -> bool {
+		self.exclude_modules || self.config_only
+	}
+
+	fn skips_readme(&self) -> bool {
+		self.exclude_readme || self.config_only
+	}
+
+	/// Whether the field with this `state::field_key` is left alone by the pull.
+	fn excludes(&self, key: &str) -> bool {
+		match key {
+			"main" => self.skips_main(),
+			"description" => self.skips_readme(),
+			key => key.starts_with("modules/") && self.skips_modules(),
+		}
+	}
+}
+
+/// Pulls a project from fumosclub and links it via fumosync.json. Pulling into an existing
+/// project refreshes it from the remote instead, keeping local configuration such as aliases.
+pub async fn pull_project(
+	script_id: String,
+	project_directory: PathBuf,
+	format: ConfigFormat,
+	options: &PullOptions,
+	client: &Client,
+) -> Result<(), Error> {
+	let project_directory = normalize_path(&project_directory);
+	let existing = match read_configuration(&project_directory).await {
+		Ok(configuration) => Some(configuration),
+		Err(Error::NotAFumosyncProject(_)) if options.dry_run => {
+			info!(
+				"{} isn't a fumosync project, so there's nothing to prune",
+				project_directory.display()
+			);
+			return Ok(());
+		}
+		Err(Error::NotAFumosyncProject(_)) if options.no_init_scaffold => {
+			tokio::fs::create_dir_all(&project_directory)
+				.await
+				.with_path(Error::CreateDirectory, &project_directory)?;
+			None
+		}
+		Err(Error::NotAFumosyncProject(_)) => {
+			// setup initial file structure for hydration
+			match init(project_directory.clone(), None, format).await {
+				Ok(_) => {}
+				Err(e) => return Err(Error::ProjectDidntInitialize(Box::new(e))),
+			};
+			None
+		}
+		Err(e) => return Err(e),
+	};
 
-	write_file(
-		project_directory.join("init.server.luau"),
-		&script_info.source.main,
+	let script_info = cancellable(
+		&options.cancel,
+		client
+			.get_editor(&script_id)
+			.instrument(info_span!("network")),
 	)
-	.await?;
+	.await?
+	.script_info;
+	hydrate(
+		&project_directory,
+		script_id,
+		script_info,
+		existing,
+		format,
+		options,
+	)
+	.await
+}
+
+/// Writes the fetched `script_info` into the project at `project_directory`, refreshing it when
+/// it has an `existing` configuration.
+async fn hydrate(
+	project_directory: &Path,
+	script_id: String,
+	mut script_info: EditorScriptInfo,
+	existing: Option<Configuration>,
+	format: ConfigFormat,
+	options: &PullOptions,
+) -> Result<(), Error> {
+	// normalized before anything is hashed, so the next push sees the written files as synced
+	if let Some(policy) = existing
+		.as_ref()
+		.and_then(|configuration| configuration.trailing_newline)
+	{
+		policy.apply(&mut script_info.source.main);
+		for source in script_info.source.modules.values_mut() {
+			policy.apply(source);
+		}
+	}
+
+	let span = info_span!(
+		"file_writes",
+		modules = script_info.source.modules.len(),
+		bytes = script_info.source.main.len()
+			+ script_info.description.len()
+			+ script_info
+				.source
+				.modules
+				.values()
+				.map(String::len)
+				.sum::<usize>()
+	);
+	let pruned = match &existing {
+		Some(configuration) if options.prune => {
+			prune_candidates(project_directory, configuration, &script_info, options).await?
+		}
+		_ => Vec::new(),
+	};
+	if options.dry_run {
+		for module in &pruned {
+			println!("would delete {}", module.path.display());
+		}
+		return Ok(());
+	}
 
-	write_file(
-		project_directory.join("fumosync.json"),
-		&serde_json::to_string_pretty(&Configuration {
-			script_name: script_info.name,
-			script_id,
-			whitelist: script_info.whitelist,
-			is_public: script_info.is_public,
-		})?,
+	let scaffolded = existing.is_some() || !options.no_init_scaffold;
+	let module_roots: Vec<PathBuf> = existing
+		.iter()
+		.flat_map(|configuration| configuration.module_directories())
+		.map(|directory| project_directory.join(directory))
+		.collect();
+	let mut state = State::read(project_directory).await?;
+	let previous = std::mem::take(&mut state.synced);
+	state.mark_pulled(&script_info);
+
+	let written = write_project(
+		project_directory,
+		script_id,
+		script_info,
+		existing,
+		format,
+		options,
+		&previous,
 	)
+	.instrument(span)
 	.await?;
 
-	for (name, source) in script_info.source.modules {
-		write_file(
-			project_directory.join("pkg").join(format!("{name}.luau")),
-			&source,
-		)
-		.await?;
+	// fields whose local files weren't replaced keep whatever was last synced
+	for key in state.synced.keys().cloned().collect::<Vec<_>>() {
+		if options.excludes(&key) || written.kept.contains(&key) {
+			match previous.get(&key) {
+				Some(fingerprint) => state.synced.insert(key, fingerprint.clone()),
+				None => state.synced.remove(&key),
+			};
+		}
+	}
+	state.synced.extend(written.formatted);
+	for module in &pruned {
+		tokio::fs::remove_file(&module.path)
+			.await
+			.with_path(Error::RemoveFile, &module.path)?;
+		info!("deleted {}", module.path.display());
+	}
+	if options.prune_empty_dirs {
+		for module in &pruned {
+			remove_empty_parents(&module.path, &module_roots).await;
+		}
 	}
 
-	Ok(())
+	match scaffolded {
+		true => state.write(project_directory).await,
+		false => Ok(()),
+	}
 }
 
-pub async fn read_file<T: AsRef<Path>>(path: T) -> Result<String, Error> {
-	match tokio::fs::read_to_string(path.as_ref()).await {
-		Ok(value) => Ok(value),
-		Err(io_error) => Err(Error::ReadFile(path.as_ref().to_path_buf(), io_error)),
+/// Removes the directories holding `path` as long as they're empty, stopping at (and keeping) the
+/// module directory in `roots` it's under; paths outside of every root are left alone.
+async fn remove_empty_parents(path: &Path, roots: &[PathBuf]) {
+	let roots: Vec<PathBuf> = roots.iter().map(|root| normalize_path(root)).collect();
+	for directory in path.ancestors().skip(1) {
+		let normalized = normalize_path(directory);
+		if roots.contains(&normalized) || !roots.iter().any(|root| normalized.starts_with(root)) {
+			return;
+		}
+		// fails on directories that still hold something, which is where this stops
+		if tokio::fs::remove_dir(directory).await.is_err() {
+			return;
+		}
+		info!("removed empty directory {}", directory.display());
 	}
 }
 
-pub async fn push_project() -> Result<(), Error> {
-	let configuration = read_configuration().await?;
-	let whitelist = configuration.whitelist.iter().map(|x| x.as_str()).collect();
+/// Local modules the remote script doesn't have, except those a source that stays after the pull
+/// still requires, which are warned about instead; none when the pull excludes modules.
+async fn prune_candidates(
+	root: &Path,
+	configuration: &Configuration,
+	remote: &EditorScriptInfo,
+	options: &PullOptions,
+) -> Result<Vec<LocalModule>, Error> {
+	// modules left out of the pull aren't orphaned, whatever the remote holds
+	if options.skips_modules() {
+		info!("not pruning modules, since they're excluded from the pull");
+		return Ok(Vec::new());
+	}
+	let mut modules =
+		discover_all_modules(root, configuration, false, Diagnostics::default()).await?;
+	apply_aliases(root, &configuration.aliases, &mut modules).await?;
+	modules.retain(|module| !remote.source.modules.contains_key(&module.name));
 
-	let description = &read_file("README.md").await?;
-	let main_source = &read_file("init.server.luau").await?;
+	let main_path = configuration.main_path(root);
+	let main_source = match options.skips_main() {
+		true => read_file(&main_path).await.unwrap_or_default(),
+		false => remote.source.main.clone(),
+	};
+	let remaining: Vec<(PathBuf, &str)> = std::iter::once((main_path, main_source.as_str()))
+		.chain(
+			remote
+				.source
+				.modules
+				.iter()
+				.map(|(name, source)| (configuration.module_path(root, name), source.as_str())),
+		)
+		.collect();
 
-	let mut actions: Vec<EditorUpdate> = Vec::from([
-		EditorUpdate::Name(&configuration.script_name),
-		EditorUpdate::Whitelist(whitelist),
-		EditorUpdate::Publicity(configuration.is_public),
-		EditorUpdate::Description(description),
-		EditorUpdate::MainSource(main_source),
-	]);
+	let mut candidates = Vec::with_capacity(modules.len());
+	for module in modules {
+		let required_by: Vec<String> = remaining
+			.iter()
+			.filter(|(_, source)| required_modules(source).contains(&module.name.as_str()))
+			.map(|(path, _)| path.display().to_string())
+			.collect();
+		match required_by.is_empty() {
+			true => candidates.push(module),
+			false => warn!(
+				target: "safety",
+				"not pruning {}: it's gone remotely, but {} still require it",
+				module.path.display(),
+				required_by.join(", ")
+			),
+		}
+	}
+	Ok(candidates)
+}
 
-	let mut modules: Vec<(String, String)> = Vec::new();
+/// What `write_project` wrote besides the remote contents, keyed like `State::synced`.
+struct Written {
+	/// Fields whose local files were left without the remote contents.
+	kept: Vec<String>,
+	/// Hashes of the sources the formatter changed, as they are on disk now.
+	formatted: BTreeMap<String, String>,
+}
 
-	let pkg_path = PathBuf::from("pkg");
-	let mut stream = match tokio::fs::read_dir(&pkg_path).await {
-		Ok(value) => value,
-		Err(io_error) => return Err(Error::ReadDirectory(pkg_path, io_error)),
+/// Writes a script's contents over a freshly initialized project, or over an `existing` one whose
+/// configuration is updated rather than replaced. Files of an existing project that were edited
+/// since they were last `synced` are handled according to `options.on_conflict`. The configured
+/// formatter then runs over the sources that were written.
+async fn write_project(
+	project_directory: &Path,
+	script_id: String,
+	script_info: EditorScriptInfo,
+	existing: Option<Configuration>,
+	format: ConfigFormat,
+	options: &PullOptions,
+	synced: &BTreeMap<String, String>,
+) -> Result<Written, Error> {
+	let policy = match existing {
+		Some(_) => options.on_conflict,
+		None => ConflictPolicy::Overwrite,
 	};
-
-	while let Some(module) = stream.next_entry().await? {
-		if let Ok(file_type) = module.file_type().await {
-			if file_type.is_file()
-				&& module
-					.path()
-					.extension()
-					.unwrap_or(OsStr::new(""))
-					.to_string_lossy()
-					== "luau"
+	let scaffolded = existing.is_some() || !options.no_init_scaffold;
+	let mut configuration = match existing {
+		Some(mut configuration) => {
+			configuration.script_name = options.name.clone().unwrap_or(script_info.name);
+			configuration.script_id = script_id;
+			configuration.is_public = script_info.is_public;
+			// keep @group entries as long as they still expand to the remote whitelist
+			if resolve_whitelist(project_directory, &configuration)
+				.await
+				.ok()
+				.as_ref()
+				!= Some(&script_info.whitelist)
 			{
-				let path_without_extension = PathBuf::from(module.file_name()).with_extension("");
-				let name = path_without_extension.to_string_lossy();
-				let source: String = read_file(module.path()).await?;
-				modules.push((name.to_string(), source));
+				configuration.whitelist = script_info.whitelist;
+			}
+			configuration
+		}
+		None => Configuration::builder()
+			.script_name(options.name.clone().unwrap_or(script_info.name))
+			.script_id(script_id)
+			.whitelist(script_info.whitelist)
+			.public(script_info.is_public)
+			.build(),
+	};
+
+	let names: Vec<&str> = script_info
+		.source
+		.modules
+		.keys()
+		.map(String::as_str)
+		.collect();
+	let directory = configuration.module_directories()[0].to_path_buf();
+	alias_case_collisions(
+		&names,
+		&directory,
+		&mut configuration.aliases,
+		options.case_collisions,
+		|path| project_directory.join(path).exists(),
+	)?;
+
+	let mut files = Vec::new();
+	let description_path = configuration.description_path(project_directory);
+	match options.skips_readme() {
+		true => info!("skipping {}", description_path.display()),
+		false => files.push((
+			EditorUpdate::Description(&script_info.description),
+			description_path,
+		)),
+	}
+	let main_path = configuration.main_path(project_directory);
+	match options.skips_main() {
+		true => info!("skipping {}", main_path.display()),
+		false => files.push((
+			EditorUpdate::MainSource(&script_info.source.main),
+			main_path,
+		)),
+	}
+	match options.skips_modules() {
+		true => info!("skipping {} modules", script_info.source.modules.len()),
+		false => {
+			for (name, source) in &script_info.source.modules {
+				files.push((
+					EditorUpdate::Module { name, source },
+					configuration.module_path(project_directory, name),
+				));
 			}
-		} else {
-			info!("failed getting file type for {}", module.path().display());
 		}
 	}
 
-	// use .iter() to force items to have a lifetime bounded by the function
-	for (name, source) in modules.iter() {
-		actions.push(EditorUpdate::Module { name, source });
+	// check every path before writing anything, so a pull never stops halfway
+	for (update, path) in &files {
+		if let Some(obstruction) = obstruction(project_directory, path).await? {
+			return Err(Error::PathConflict(
+				update.label(),
+				path.clone(),
+				obstruction,
+			));
+		}
 	}
 
-	let client = Client::new(get_session_secrets().await?);
-	client
-		.set_editor(&configuration.script_id, &actions)
+	if scaffolded {
+		write_configuration(
+			configuration_path(project_directory),
+			&configuration,
+			format,
+		)
 		.await?;
-	Ok(())
+	}
+	// kept even when the script has no modules, so its layout survives any pull
+	if !options.skips_modules() {
+		let directory = project_directory.join(configuration.module_directories()[0]);
+		tokio::fs::create_dir_all(&directory)
+			.await
+			.with_path(Error::CreateDirectory, &directory)?;
+	}
+
+	let mut kept = Vec::new();
+	let mut written = Vec::new();
+	for (update, path) in files {
+		let key = field_key(&update);
+		let remote = update.text().expect("sources to have text");
+		let pushed = |local: &mut String| as_pushed(&update, local, configuration.trailing_newline);
+		match write_pulled(&path, remote, synced.get(&key), policy, pushed).await? {
+			true => written.push((update, key, path)),
+			false => kept.push(key),
+		}
+	}
+
+	let mut formatted = BTreeMap::new();
+	if let Some(formatter) = configuration
+		.formatter
+		.as_ref()
+		.or(shared_defaults().formatter.as_ref())
+	{
+		let paths: Vec<PathBuf> = written.iter().map(|(_, _, path)| path.clone()).collect();
+		format_sources(project_directory, formatter, &paths).await;
+		// the formatted files count as synced (hashed as a push would send them), so they don't
+		// look edited locally
+		for (update, key, path) in written {
+			let mut local = read_file(&path).await?;
+			as_pushed(&update, &mut local, configuration.trailing_newline);
+			if Some(local.as_str()) != update.text() {
+				formatted.insert(key, hash(&local));
+			}
+		}
+	}
+
+	Ok(Written { kept, formatted })
+}
+
+/// What keeps a file from being written at `path`: a directory at the path itself, or a file
+/// where one of its parent directories (up to `root`) should be.
+pub async fn obstruction(root: &Path, path: &Path) -> Result<Option<PathBuf>, Error> {
+	if tokio::fs::metadata(path)
+		.await
+		.is_ok_and(|metadata| metadata.is_dir())
+	{
+		return Ok(Some(path.to_path_buf()));
+	}
+
+	for ancestor in path.ancestors().skip(1) {
+		if ancestor == root || !ancestor.starts_with(root) {
+			break;
+		}
+		match tokio::fs::metadata(ancestor).await {
+			Ok(metadata) if !metadata.is_dir() => return Ok(Some(ancestor.to_path_buf())),
+			Ok(_) => {}
+			Err(io_error) if io_error.kind() == io::ErrorKind::NotFound => {}
+			Err(io_error) => return Err(Error::ReadDirectory(ancestor.to_path_buf(), io_error)),
+		}
+	}
+	Ok(None)
+}
+
+/// Runs `formatter` over the `.luau` files among `paths`, warning about (but otherwise ignoring)
+/// any run that fails.
+async fn format_sources(root: &Path, formatter: &[String], paths: &[PathBuf]) {
+	let Some((program, arguments)) = formatter.split_first() else {
+		return;
+	};
+
+	for path in paths
+		.iter()
+		.filter(|path| path.extension() == Some(OsStr::new("luau")))
+	{
+		let relative = path.strip_prefix(root).unwrap_or(path);
+		let status = tokio::process::Command::new(program)
+			.args(arguments)
+			.arg(relative)
+			.current_dir(root)
+			.status()
+			.await;
+		match status {
+			Ok(status) if status.success() => {}
+			Ok(status) => warn!("{program} failed on {} ({status})", path.display()),
+			Err(io_error) => {
+				warn!("failed running {program}: {io_error}");
+				return;
+			}
+		}
+	}
+}
+
+/// Normalizes the `local` text of an update's file the way a push reads it (without
+/// `--keep-bom`), so it hashes like what was last synced.
+fn as_pushed(update: &EditorUpdate, local: &mut String, trailing_newline: Option<TrailingNewline>) {
+	if matches!(update, EditorUpdate::Description(_)) {
+		return;
+	}
+	if local.starts_with('\u{feff}') {
+		local.remove(0);
+	}
+	if let Some(policy) = trailing_newline {
+		policy.apply(local);
+	}
+}
+
+/// Writes the remote contents of a source to `path`, unless the local file was edited since it
+/// was last synced (its hash, once normalized by `pushed`, isn't `synced`) and `policy` says to
+/// keep those edits. Returns whether the file now holds the remote contents.
+async fn write_pulled(
+	path: &Path,
+	remote: &str,
+	synced: Option<&String>,
+	policy: ConflictPolicy,
+	pushed: impl Fn(&mut String),
+) -> Result<bool, Error> {
+	if let Some(parent) = path.parent() {
+		tokio::fs::create_dir_all(parent)
+			.await
+			.with_path(Error::CreateDirectory, parent)?;
+	}
+
+	if policy != ConflictPolicy::Overwrite && tokio::fs::try_exists(path).await? {
+		let local = read_file(path).await?;
+		let mut normalized = local.clone();
+		pushed(&mut normalized);
+		if local != remote && synced != Some(&hash(&normalized)) {
+			if policy == ConflictPolicy::Skip {
+				warn!(target: "safety", "kept {}, it was edited locally", path.display());
+				return Ok(false);
+			}
+
+			let mut merged = String::new();
+			for (marker, side) in [("<<<<<<< local", local.as_str()), ("=======", remote)] {
+				merged.push_str(marker);
+				merged.push('\n');
+				merged.push_str(side);
+				if !side.is_empty() && !side.ends_with('\n') {
+					merged.push('\n');
+				}
+			}
+			merged.push_str(">>>>>>> remote\n");
+			write_file(path, &merged).await?;
+			warn!(target: "safety", "wrote conflict markers into {}", path.display());
+			return Ok(false);
+		}
+	}
+
+	write_file(path, remote).await?;
+	Ok(true)
+}
+
+/// Makes the freshly initialized project at `directory` a git repository with everything
+/// committed, ignoring fumosync's local state and .env (which may hold a session). A missing or
+/// failing git is only warned about, since the project itself is already in place.
+pub async fn init_git(directory: &Path) -> Result<(), Error> {
+	write_file(directory.join(".gitignore"), "/.fumosync/\n/.env\n").await?;
+
+	for arguments in [
+		&["init", "--quiet"][..],
+		&["add", "--all"],
+		&[
+			"commit",
+			"--quiet",
+			"--message",
+			"Initialize fumosync project",
+		],
+	] {
+		let status = tokio::process::Command::new("git")
+			.args(arguments)
+			.current_dir(directory)
+			.status()
+			.await;
+		match status {
+			Ok(status) if status.success() => {}
+			Ok(status) => {
+				warn!(
+					"`git {}` failed ({status}), leaving the repository as is",
+					arguments[0]
+				);
+				return Ok(());
+			}
+			Err(io_error) if io_error.kind() == io::ErrorKind::NotFound => {
+				warn!("git isn't installed, not creating a repository");
+				return Ok(());
+			}
+			Err(io_error) => return Err(io_error.into()),
+		}
+	}
+
+	Ok(())
+}
+
+/// Pulls a script into a scratch directory and checks that pushing it straight back would
+/// change nothing, reporting every file that doesn't survive the round trip.
+pub async fn verify_roundtrip(script_id: String, client: &Client) -> Result<(), Error> {
+	let remote = client.get_editor(&script_id).await?.script_info;
+
+	// a fresh directory only this process can know about, removed again when dropped
+	let scratch = tempfile::Builder::new()
+		.prefix("fumosync-roundtrip-")
+		.tempdir()?;
+	let divergences =
+		roundtrip_divergences(&scratch.path().join("project"), script_id.clone(), remote).await;
+	if let Err(io_error) = scratch.close() {
+		warn!("failed cleaning up the round trip directory: {io_error}");
+	}
+
+	let divergences = divergences?;
+	if divergences.is_empty() {
+		info!("{script_id} survives a pull and push unchanged");
+		return Ok(());
+	}
+
+	for (path, reason) in &divergences {
+		warn!(target: "fidelity", "{}: {reason}", path.display());
+	}
+	Err(Error::RoundtripDiverged(divergences.len()))
+}
+
+async fn roundtrip_divergences(
+	directory: &Path,
+	script_id: String,
+	remote: EditorScriptInfo,
+) -> Result<Vec<(PathBuf, String)>, Error> {
+	match init(directory.to_path_buf(), None, ConfigFormat::Pretty).await {
+		Ok(_) => {}
+		Err(e) => return Err(Error::ProjectDidntInitialize(Box::new(e))),
+	};
+	write_project(
+		directory,
+		script_id,
+		remote.clone(),
+		None,
+		ConfigFormat::Pretty,
+		&PullOptions::default(),
+		&BTreeMap::new(),
+	)
+	.await?;
+
+	let project = LocalProject::read(directory, &PushOptions::default()).await?;
+	let mut divergences = Vec::new();
+
+	for update in project.updates() {
+		if update.is_applied_to(&remote) {
+			continue;
+		}
+
+		let reason = describe_divergence(&update, &remote);
+		divergences.push((project.source_of(&update), reason));
+	}
+
+	for name in remote.source.modules.keys() {
+		if !project.modules.iter().any(|module| &module.name == name) {
+			divergences.push((
+				project.configuration.module_path(directory, name),
+				format!("module {name} wasn't read back"),
+			));
+		}
+	}
+
+	Ok(divergences)
+}
+
+/// How the remote script differs from what `update` would write to it.
+fn describe_divergence(update: &EditorUpdate, remote: &EditorScriptInfo) -> String {
+	match update {
+		EditorUpdate::Description(local) => describe_source_change(&remote.description, local),
+		EditorUpdate::MainSource(local) => describe_source_change(&remote.source.main, local),
+		EditorUpdate::Module { name, source } => match remote.source.modules.get(*name) {
+			Some(remote) => describe_source_change(remote, source),
+			None => format!("module {name} doesn't exist remotely"),
+		},
+		EditorUpdate::Name(_) => "scriptName differs".to_owned(),
+		EditorUpdate::Whitelist(_) => "whitelist differs".to_owned(),
+		EditorUpdate::Publicity(_) => "isPublic differs".to_owned(),
+	}
+}
+
+/// Warns about every update that `stored`, the script as fetched right after pushing, doesn't
+/// hold exactly.
+fn verify_pushed(
+	actions: &[EditorUpdate],
+	stored: &EditorScriptInfo,
+	diagnostics: Diagnostics,
+) -> Result<(), Error> {
+	let mut drifted = 0;
+	for action in actions {
+		if !action.is_applied_to(stored) {
+			drifted += 1;
+			diagnostics.warn(
+				Category::Fidelity,
+				format!(
+					"the server didn't store the {} as sent: {}",
+					action.label(),
+					describe_divergence(action, stored)
+				),
+			)?;
+		}
+	}
+	if drifted == 0 {
+		info!(
+			"verified that the server stored all {} updates as sent",
+			actions.len()
+		);
+	}
+	Ok(())
+}
+
+fn describe_source_change(remote: &str, local: &str) -> String {
+	if remote.trim_end() == local.trim_end() {
+		"differs only in trailing whitespace".to_owned()
+	} else {
+		format!(
+			"contents differ (remote {} bytes, local {} bytes)",
+			remote.len(),
+			local.len()
+		)
+	}
+}
+
+pub async fn read_file<T: AsRef<Path>>(path: T) -> Result<String, Error> {
+	tokio::fs::read_to_string(path.as_ref())
+		.await
+		.with_path(Error::ReadFile, path)
+}
+
+/// Expands `@group` whitelist entries from the groups file, dropping duplicate members.
+async fn resolve_whitelist(
+	root: &Path,
+	configuration: &Configuration,
+) -> Result<Vec<String>, Error> {
+	if !configuration
+		.whitelist
+		.iter()
+		.any(|entry| entry.starts_with('@'))
+	{
+		return Ok(configuration.whitelist.clone());
+	}
+
+	let groups_path = configuration.groups_path(root);
+	let groups: HashMap<String, Vec<String>> = serde_json::from_str(&read_file(&groups_path).await?)?;
+
+	let mut seen = HashSet::new();
+	let mut whitelist = Vec::new();
+	for entry in &configuration.whitelist {
+		let members = match entry.strip_prefix('@') {
+			Some(group) => groups
+				.get(group)
+				.with_context(|| Error::UndefinedGroup(group.to_owned(), groups_path.clone()))?
+				.as_slice(),
+			None => std::slice::from_ref(entry),
+		};
+
+		for member in members {
+			if seen.insert(member.as_str()) {
+				whitelist.push(member.clone());
+			}
+		}
+	}
+
+	Ok(whitelist)
+}
+
+/// Reads the modules of every configured module directory, resolving modules of the same name
+/// in different directories as `module_collisions` says.
+async fn discover_all_modules(
+	root: &Path,
+	configuration: &Configuration,
+	include_hidden: bool,
+	diagnostics: Diagnostics,
+) -> Result<Vec<LocalModule>, Error> {
+	let mut modules: Vec<LocalModule> = Vec::new();
+	let extensions = configuration.module_extensions();
+	for directory in configuration.module_directories() {
+		let directory = root.join(directory);
+		for module in discover_modules(&directory, &extensions, include_hidden, diagnostics).await? {
+			match modules.iter().position(|other| other.name == module.name) {
+				Some(index) if configuration.module_collisions == Some(ModuleCollisions::Error) => {
+					return Err(Error::ModuleNameCollision(
+						module.name,
+						modules[index].path.clone(),
+						module.path,
+					));
+				}
+				Some(index) => modules[index] = module,
+				None => modules.push(module),
+			}
+		}
+	}
+	Ok(modules)
+}
+
+/// Reads every file directly inside of `pkg_path` ending in one of `extensions` as a module; a
+/// missing directory has no modules. `foo.module.luau` is read as the module `foo`, while
+/// `.server.luau` and `.client.luau` files are refused since modules can't be scripts.
+///
+/// Files whose names start with a `.` (like `.scratch.luau`) are skipped unless `include_hidden`
+/// is set.
+async fn discover_modules(
+	pkg_path: &Path,
+	extensions: &[&str],
+	include_hidden: bool,
+	diagnostics: Diagnostics,
+) -> Result<Vec<LocalModule>, Error> {
+	let mut modules: Vec<LocalModule> = Vec::new();
+
+	let pkg_path = pkg_path.to_path_buf();
+	match tokio::fs::metadata(&pkg_path).await {
+		Ok(metadata) if metadata.is_dir() => {}
+		Ok(_) => return Err(Error::NotADirectory(pkg_path)),
+		Err(io_error) if io_error.kind() == io::ErrorKind::NotFound => return Ok(modules),
+		Err(io_error) => return Err(Error::ReadDirectory(pkg_path, io_error)),
+	}
+
+	let mut stream = tokio::fs::read_dir(&pkg_path)
+		.await
+		.with_path(Error::ReadDirectory, &pkg_path)?;
+
+	while let Some(module) = stream.next_entry().await? {
+		if !include_hidden && module.file_name().to_string_lossy().starts_with('.') {
+			continue;
+		}
+
+		if let Ok(file_type) = module.file_type().await {
+			let file_name = module.file_name().to_string_lossy().into_owned();
+			// the longest extension wins, so `model.json` beats `json`
+			let name = extensions
+				.iter()
+				.filter_map(|extension| file_name.strip_suffix(&format!(".{extension}")))
+				.filter(|name| !name.is_empty())
+				.min_by_key(|name| name.len());
+			if let (true, Some(name)) = (file_type.is_file(), name) {
+				// Rojo style suffixes; fumosclub only has one kind of module, so only `.module` fits
+				let name = match name.rsplit_once('.') {
+					Some((name, "module")) => name,
+					Some((_, "server" | "client")) => {
+						return Err(Error::UnsupportedScriptKind(module.path()));
+					}
+					_ => name,
+				};
+				if let Some(other) = modules.iter().find(|other| other.name == name) {
+					return Err(Error::ModuleNameCollision(
+						name.to_owned(),
+						other.path.clone(),
+						module.path(),
+					));
+				}
+				let source: String = read_file(module.path()).await?;
+				modules.push(LocalModule {
+					name: name.to_owned(),
+					path: module.path(),
+					source,
+				});
+			}
+		} else {
+			diagnostics.warn(
+				Category::Fidelity,
+				format!(
+					"failed getting file type for {}, skipping it",
+					module.path().display()
+				),
+			)?;
+		}
+	}
+
+	Ok(modules)
+}
+
+/// Renames discovered modules according to `aliases` (path -> module name), reading aliased files
+/// the scan didn't pick up, then makes sure no two modules ended up with the same name.
+async fn apply_aliases(
+	root: &Path,
+	aliases: &BTreeMap<PathBuf, String>,
+	modules: &mut Vec<LocalModule>,
+) -> Result<(), Error> {
+	for (path, name) in aliases {
+		let path = root.join(path);
+		match modules.iter_mut().find(|module| module.path == path) {
+			Some(module) => module.name = name.clone(),
+			None => modules.push(LocalModule {
+				name: name.clone(),
+				source: read_file(&path).await?,
+				path,
+			}),
+		}
+	}
+
+	let mut seen: HashMap<&str, &Path> = HashMap::new();
+	for module in modules.iter() {
+		if let Some(other) = seen.insert(&module.name, &module.path) {
+			return Err(Error::ModuleNameCollision(
+				module.name.clone(),
+				other.to_path_buf(),
+				module.path.clone(),
+			));
+		}
+	}
+
+	Ok(())
+}
+
+/// Removes the UTF-8 byte order mark some editors put at the start of files, which would end up
+/// in the pushed source.
+fn strip_bom(path: &Path, source: &mut String, diagnostics: Diagnostics) -> Result<(), Error> {
+	if source.starts_with('\u{feff}') {
+		diagnostics.warn(
+			Category::Fidelity,
+			format!(
+				"{} starts with a byte order mark, stripping it (pass --keep-bom to keep it)",
+				path.display()
+			),
+		)?;
+		source.remove(0);
+	}
+	Ok(())
+}
+
+/// Gives every module of `names` whose name only differs by case from an earlier one (and that
+/// has no alias yet) an alias to a free `{name}_N.luau` in `directory`, so no two module files
+/// differ only by case. Paths are compared case-folded against every module file, alias and
+/// suffix handed out so far; `exists` says whether something is already at a path.
+fn alias_case_collisions(
+	names: &[&str],
+	directory: &Path,
+	aliases: &mut BTreeMap<PathBuf, String>,
+	policy: CaseCollisions,
+	exists: impl Fn(&Path) -> bool,
+) -> Result<(), Error> {
+	let folded = |path: &Path| path.to_string_lossy().to_lowercase();
+	let mut taken: HashSet<String> = aliases.keys().map(|path| folded(path)).collect();
+	for name in names {
+		if !aliases.values().any(|alias| alias == name) {
+			taken.insert(folded(&directory.join(format!("{name}.luau"))));
+		}
+	}
+
+	for (earlier, later) in case_collisions(names.iter().copied()) {
+		if aliases.values().any(|alias| alias == later) {
+			continue;
+		}
+		if policy == CaseCollisions::Error {
+			return Err(Error::CaseCollision(earlier.to_owned(), later.to_owned()));
+		}
+
+		let path = (2..)
+			.map(|index| directory.join(format!("{later}_{index}.luau")))
+			.find(|candidate| !taken.contains(&folded(candidate)) && !exists(candidate))
+			.expect("some suffix to be free");
+		warn!(
+			target: "fidelity",
+			"modules {earlier} and {later} only differ by case; writing {later} to {}",
+			path.display()
+		);
+		taken.insert(folded(&path));
+		aliases.insert(path, later.to_owned());
+	}
+	Ok(())
+}
+
+/// Pairs of an earlier and a later entry of `names` that only differ by case.
+fn case_collisions<'a>(names: impl Iterator<Item = &'a str>) -> Vec<(&'a str, &'a str)> {
+	let mut seen: HashMap<String, &str> = HashMap::new();
+	let mut collisions = Vec::new();
+	for name in names {
+		match seen.get(&name.to_lowercase()) {
+			Some(earlier) => collisions.push((*earlier, name)),
+			None => {
+				seen.insert(name.to_lowercase(), name);
+			}
+		}
+	}
+	collisions
+}
+
+/// Names passed to `requireM` as string literals, like `requireM("name")` or `requireM 'name'`.
+fn required_modules(source: &str) -> Vec<&str> {
+	require_spans(source)
+		.into_iter()
+		.map(|span| &source[span])
+		.collect()
+}
+
+/// Byte ranges of the names passed to `requireM` as string literals, without their quotes.
+fn require_spans(source: &str) -> Vec<Range<usize>> {
+	source
+		.match_indices("requireM")
+		.filter_map(|(index, _)| {
+			let rest = source[index + "requireM".len()..].trim_start();
+			let rest = rest.strip_prefix('(').unwrap_or(rest).trim_start();
+			let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+			let start = source.len() - rest.len() + 1;
+			source[start..].find(quote).map(|end| start..start + end)
+		})
+		.collect()
+}
+
+/// `source` with every `requireM` of the module `old` requiring `new` instead, or `None` when
+/// nothing requires `old`.
+pub fn rename_requires(source: &str, old: &str, new: &str) -> Option<String> {
+	let spans: Vec<Range<usize>> = require_spans(source)
+		.into_iter()
+		.filter(|span| &source[span.clone()] == old)
+		.collect();
+	if spans.is_empty() {
+		return None;
+	}
+
+	let mut renamed = String::with_capacity(source.len());
+	let mut copied = 0;
+	for span in spans {
+		renamed.push_str(&source[copied..span.start]);
+		renamed.push_str(new);
+		copied = span.end;
+	}
+	renamed.push_str(&source[copied..]);
+	Some(renamed)
+}
+
+/// A project as read from disk, ready to be turned into `EditorUpdate`s.
+pub struct LocalProject {
+	pub root: PathBuf,
+	pub configuration: Configuration,
+	/// The whitelist with `@group` entries expanded.
+	pub whitelist: Vec<String>,
+	pub description_path: PathBuf,
+	pub description: String,
+	pub main_path: PathBuf,
+	pub main_source: String,
+	pub modules: Vec<LocalModule>,
+}
+
+pub struct LocalModule {
+	pub name: String,
+	pub path: PathBuf,
+	pub source: String,
+}
+
+impl LocalProject {
+	/// Reads the project rooted at `root`.
+	pub async fn read(root: &Path, options: &PushOptions) -> Result<Self, Error> {
+		let (configuration, whitelist) = async {
+			let mut configuration = read_configuration(root).await?;
+			if let Some(name) = &options.env {
+				configuration.apply_environment(name)?;
+			}
+			let whitelist = resolve_whitelist(root, &configuration).await?;
+			Ok::<_, Error>((configuration, whitelist))
+		}
+		.instrument(info_span!("config_read"))
+		.await?;
+
+		let main_path = configuration.main_path(root);
+		let description_path = configuration.description_path(root);
+
+		let span = info_span!("file_reads", modules = field::Empty, bytes = field::Empty);
+		let (mut main_source, description, mut modules) = async {
+			let main_source = if tokio::fs::try_exists(&main_path).await? {
+				read_file(&main_path).await?
+			} else if options.allow_empty_main {
+				options.diagnostics.warn(
+					Category::Fidelity,
+					format!(
+						"{} doesn't exist, pushing an empty main source",
+						main_path.display()
+					),
+				)?;
+				String::new()
+			} else {
+				return Err(Error::MissingMainSource(main_path.clone()));
+			};
+
+			if !tokio::fs::try_exists(&description_path).await? {
+				return Err(Error::MissingDescription(description_path.clone()));
+			}
+			let description = read_file(&description_path).await?;
+
+			let modules = match &configuration.files {
+				Some(files) => {
+					let mut modules = Vec::with_capacity(files.modules.len());
+					for (name, path) in &files.modules {
+						let path = root.join(path);
+						modules.push(LocalModule {
+							name: name.clone(),
+							source: read_file(&path).await?,
+							path,
+						});
+					}
+					modules
+				}
+				None => {
+					let mut modules = discover_all_modules(
+						root,
+						&configuration,
+						options.include_hidden,
+						options.diagnostics,
+					)
+					.await?;
+					apply_aliases(root, &configuration.aliases, &mut modules).await?;
+					modules
+				}
+			};
+
+			Ok((main_source, description, modules))
+		}
+		.instrument(span.clone())
+		.await?;
+
+		if !options.keep_bom {
+			strip_bom(&main_path, &mut main_source, options.diagnostics)?;
+			for module in &mut modules {
+				strip_bom(&module.path, &mut module.source, options.diagnostics)?;
+			}
+		}
+		if let Some(policy) = configuration.trailing_newline {
+			policy.apply(&mut main_source);
+			for module in &mut modules {
+				policy.apply(&mut module.source);
+			}
+		}
+
+		span.record("modules", modules.len());
+		span.record(
+			"bytes",
+			main_source.len()
+				+ description.len()
+				+ modules
+					.iter()
+					.map(|module| module.source.len())
+					.sum::<usize>(),
+		);
+
+		Ok(Self {
+			root: root.to_path_buf(),
+			configuration,
+			whitelist,
+			description_path,
+			description,
+			main_path,
+			main_source,
+			modules,
+		})
+	}
+
+	/// Orders modules as in `order` (the remote's order at the last pull), with modules it
+	/// doesn't list following alphabetically.
+	pub fn sort_modules(&mut self, order: &[String]) {
+		let position = |name: &str| order.iter().position(|known| known == name);
+		self
+			.modules
+			.sort_by(|a, b| match (position(&a.name), position(&b.name)) {
+				(Some(a), Some(b)) => a.cmp(&b),
+				(Some(_), None) => Ordering::Less,
+				(None, Some(_)) => Ordering::Greater,
+				(None, None) => a.name.cmp(&b.name),
+			});
+	}
+
+	/// Warns about modules that no `requireM` call uses and about `requireM` calls naming a module
+	/// that doesn't exist. Only calls with a literal string argument are understood.
+	pub fn lint_requires(&self, diagnostics: Diagnostics) -> Result<(), Error> {
+		let sources = std::iter::once((&self.main_path, &self.main_source)).chain(
+			self
+				.modules
+				.iter()
+				.map(|module| (&module.path, &module.source)),
+		);
+
+		let mut required = HashSet::new();
+		for (path, source) in sources {
+			for name in required_modules(source) {
+				if !self.modules.iter().any(|module| module.name == name) {
+					diagnostics.warn(
+						Category::Lint,
+						format!("{} requires {name}, which isn't a module", path.display()),
+					)?;
+				}
+				required.insert(name);
+			}
+		}
+
+		for module in &self.modules {
+			if !required.contains(module.name.as_str()) {
+				diagnostics.warn(
+					Category::Lint,
+					format!("module {} is never required", module.name),
+				)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Every update needed to make the remote script match this project.
+	pub fn updates(&self) -> Vec<EditorUpdate<'_>> {
+		let mut updates = Vec::from([
+			EditorUpdate::Name(&self.configuration.script_name),
+			EditorUpdate::Whitelist(self.whitelist.iter().map(|x| x.as_str()).collect()),
+			EditorUpdate::Publicity(self.configuration.is_public),
+			EditorUpdate::Description(&self.description),
+			EditorUpdate::MainSource(&self.main_source),
+		]);
+
+		for module in &self.modules {
+			updates.push(EditorUpdate::Module {
+				name: &module.name,
+				source: &module.source,
+			});
+		}
+
+		updates
+	}
+
+	/// The main source with every module embedded, for pushing the project as a single script.
+	///
+	/// Modules become functions in a table, in name order so the output only changes when the
+	/// sources do. The injected shim shadows the global `requireM` with a local one that runs an
+	/// embedded module the first time it's required and returns the cached result after that;
+	/// names it doesn't embed are passed on to the global `requireM`. The main source follows in a
+	/// `do` block, so its locals don't mix with the shim's.
+	pub fn bundle(&self) -> String {
+		let mut modules: Vec<&LocalModule> = self.modules.iter().collect();
+		modules.sort_by(|a, b| a.name.cmp(&b.name));
+
+		let mut bundle = String::from(
+			r#"-- bundled by fumosync; modules are embedded below and requireM loads them from here
+local __fumosync_requireM = requireM
+local __fumosync_modules = {}
+local __fumosync_loaded = {}
+local function requireM(name)
+	local loaded = __fumosync_loaded[name]
+	if loaded then
+		return loaded[1]
+	end
+	local module = __fumosync_modules[name]
+	if not module then
+		return __fumosync_requireM(name)
+	end
+	local result = module()
+	__fumosync_loaded[name] = { result }
+	return result
+end
+"#,
+		);
+		for module in modules {
+			bundle.push_str(&format!(
+				"\n__fumosync_modules[{:?}] = function()\n{}\nend\n",
+				module.name, module.source
+			));
+		}
+		bundle.push_str(&format!("\ndo\n{}\nend\n", self.main_source));
+		bundle
+	}
+
+	/// The file an update was read from.
+	pub fn source_of(&self, update: &EditorUpdate) -> PathBuf {
+		match update {
+			EditorUpdate::Description(_) => self.description_path.clone(),
+			EditorUpdate::MainSource(_) => self.main_path.clone(),
+			EditorUpdate::Module { name, .. } => self
+				.modules
+				.iter()
+				.find(|module| module.name == *name)
+				.map(|module| module.path.clone())
+				.unwrap_or_default(),
+			EditorUpdate::Name(_) | EditorUpdate::Whitelist(_) | EditorUpdate::Publicity(_) => {
+				configuration_path(&self.root)
+			}
+		}
+	}
+
+	/// Keeps the updates read from any of the (normalized) `changed` files; the whitelist is also
+	/// read from the groups file its `@group` entries expand from.
+	fn retain_changed(&self, actions: &mut Vec<EditorUpdate>, changed: &HashSet<PathBuf>) {
+		let groups_path = normalize_path(&self.configuration.groups_path(&self.root));
+		actions.retain(|action| {
+			changed.contains(&normalize_path(&self.source_of(action)))
+				|| (matches!(action, EditorUpdate::Whitelist(_)) && changed.contains(&groups_path))
+		});
+	}
+}
+
+/// What a push did, or with `dry_run` would have done.
+#[derive(Serialize, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PushReport {
+	pub script_id: String,
+	/// Keys of the non-module fields sent, like `main` or `whitelist`.
+	pub fields: Vec<String>,
+	/// Names of the modules sent.
+	pub modules: Vec<String>,
+	/// Labels of what was left out on purpose, by a `--no-*` flag or `--select`, or for being blank.
+	pub skipped: Vec<String>,
+	/// Labels of what wasn't sent because it was already up to date.
+	pub unchanged: Vec<String>,
+	/// Labels of what wasn't sent because the remote holds what the server made of it last time.
+	pub normalized: Vec<String>,
+	pub updates: usize,
+	pub dry_run: bool,
+	pub timestamp: DateTime<Utc>,
+	pub duration_ms: u64,
+	/// Labels of everything that could have been sent.
+	#[serde(skip)]
+	candidates: Vec<String>,
+	/// Labels of what was sent.
+	#[serde(skip)]
+	sent: Vec<String>,
+}
+
+impl PushReport {
+	fn record(&mut self, actions: &[EditorUpdate]) {
+		self.updates = actions.len();
+		self.sent = actions.iter().map(EditorUpdate::label).collect();
+		for action in actions {
+			match action {
+				EditorUpdate::Module { name, .. } => self.modules.push(name.to_string()),
+				action => self.fields.push(field_key(action)),
+			}
+		}
+	}
+
+	/// Counts every candidate that was neither sent nor skipped as unchanged.
+	fn finish(&mut self, started: Instant) {
+		self.unchanged = self
+			.candidates
+			.iter()
+			.filter(|label| {
+				!self.sent.contains(label)
+					&& !self.skipped.contains(label)
+					&& !self.normalized.contains(label)
+			})
+			.cloned()
+			.collect();
+		self.timestamp = Utc::now();
+		self.duration_ms = started.elapsed().as_millis() as u64;
+	}
+
+	/// One line counting what was sent, skipped, unchanged and normalized.
+	pub fn summary(&self) -> String {
+		format!(
+			"{} {}, {} skipped, {} unchanged, {} normalized",
+			self.sent.len(),
+			match self.dry_run {
+				true => "would be sent",
+				false => "sent",
+			},
+			self.skipped.len(),
+			self.unchanged.len(),
+			self.normalized.len()
+		)
+	}
+}
+
+/// What `push --summary` writes.
+#[derive(Serialize)]
+struct PushSummary<'a> {
+	#[serde(flatten)]
+	report: &'a PushReport,
+	success: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<String>,
+}
+
+/// Pushes the project in the current directory, describing what was sent in the returned report.
+pub async fn push_project(options: &PushOptions, client: &Client) -> Result<PushReport, Error> {
+	let started = Instant::now();
+	let mut report = PushReport {
+		dry_run: options.dry_run,
+		..PushReport::default()
+	};
+	let result = push(options, client, &mut report).await;
+	report.finish(started);
+
+	if let Some(path) = &options.summary {
+		let summary = PushSummary {
+			report: &report,
+			success: result.is_ok(),
+			error: result.as_ref().err().map(ToString::to_string),
+		};
+		// the push (and its state) already happened, so failing to describe it doesn't fail it
+		let written = match serde_json::to_string_pretty(&summary) {
+			Ok(json) => write_file(path, &json).await,
+			Err(e) => Err(e.into()),
+		};
+		if let Err(e) = written {
+			warn!("failed writing the push summary to {}: {e}", path.display());
+		}
+	}
+	result.map(|()| report)
+}
+
+async fn push(
+	options: &PushOptions,
+	client: &Client,
+	report: &mut PushReport,
+) -> Result<(), Error> {
+	if options.main_stdin {
+		return push_stdin_main(options, client, report).await;
+	}
+	let mut project =
+		cancellable(&options.cancel, LocalProject::read(Path::new("."), options)).await?;
+	let max_modules = options
+		.max_modules
+		.or(
+			project
+				.configuration
+				.budget
+				.as_ref()
+				.and_then(|budget| budget.max_modules),
+		)
+		.unwrap_or(DEFAULT_MAX_MODULES);
+	if project.modules.len() > max_modules {
+		return Err(Error::TooManyModules(project.modules.len(), max_modules));
+	}
+	if !options.no_lint {
+		project.lint_requires(options.diagnostics)?;
+	}
+	if project.configuration.module_concurrency.is_some() && project.configuration.batch.is_some() {
+		options.diagnostics.warn(
+			Category::Lint,
+			"both moduleConcurrency and batch are set; modules are uploaded concurrently and batch is ignored",
+		)?;
+	}
+	let paths: Vec<String> = project
+		.modules
+		.iter()
+		.map(|module| module.path.to_string_lossy().into_owned())
+		.collect();
+	for (earlier, later) in case_collisions(paths.iter().map(String::as_str)) {
+		options.diagnostics.warn(Category::Lint, format!(
+			"{earlier} and {later} only differ by case, so they'd be the same file on case-insensitive filesystems"
+		))?;
+	}
+	project.sort_modules(&State::read(&project.root).await?.module_order);
+	if let Some(target) = &options.target {
+		project.configuration.script_id = target.clone();
+	}
+	let script_id = &project.configuration.script_id;
+	report.script_id = script_id.clone();
+	let bundled = options.bundle.then(|| project.bundle());
+	let mut actions = project.updates();
+	if let Some(bundled) = &bundled {
+		actions.retain(|action| !matches!(action, EditorUpdate::Module { .. }));
+		for action in &mut actions {
+			if let EditorUpdate::MainSource(source) = action {
+				*source = bundled;
+			}
+		}
+	}
+
+	let mut skipped: Vec<String> = actions
+		.iter()
+		.filter(|action| match action {
+			EditorUpdate::Description(_) => options.no_description,
+			EditorUpdate::Whitelist(_) => options.no_whitelist,
+			EditorUpdate::Publicity(_) => options.no_publicity,
+			_ => false,
+		})
+		.map(EditorUpdate::label)
+		.collect();
+	if !skipped.is_empty() {
+		info!("skipping {}", skipped.join(", "));
+		actions.retain(|action| !skipped.contains(&action.label()));
+	}
+	if !options.push_empty {
+		let blank: Vec<String> = actions
+			.iter()
+			.filter(|action| match action {
+				EditorUpdate::Description(text) => text.trim().is_empty(),
+				EditorUpdate::MainSource(text) => !options.allow_empty_main && text.trim().is_empty(),
+				_ => false,
+			})
+			.map(EditorUpdate::label)
+			.collect();
+		for label in &blank {
+			options.diagnostics.warn(
+				Category::Fidelity,
+				format!(
+				"the local {label} is blank, leaving the remote one alone (pass --push-empty to push it anyway)"
+			),
+			)?;
+		}
+		actions.retain(|action| !blank.contains(&action.label()));
+		skipped.extend(blank);
+	}
+	report.skipped = skipped;
+	report.candidates = actions.iter().map(EditorUpdate::label).collect();
+
+	if let Some(max_size_kb) = project
+		.configuration
+		.budget
+		.as_ref()
+		.and_then(|budget| budget.max_size_kb)
+	{
+		let size = project.main_source.len()
+			+ project
+				.modules
+				.iter()
+				.map(|module| module.source.len())
+				.sum::<usize>();
+		if size as u64 > max_size_kb * 1024 {
+			return Err(Error::SizeBudgetExceeded(size, max_size_kb));
+		}
+	}
+
+	let changed_files = match &options.since_commit {
+		Some(git_ref) => match changed_since(git_ref).await {
+			Ok(paths) => Some((git_ref, paths)),
+			Err(e) => {
+				options.diagnostics.warn(
+					Category::Other,
+					format!("can't ask git what changed since {git_ref}, comparing hashes instead: {e}"),
+				)?;
+				None
+			}
+		},
+		None => None,
+	};
+	if let Some((git_ref, paths)) = &changed_files {
+		let paths: HashSet<PathBuf> = paths
+			.iter()
+			.map(|path| normalize_path(&project.root.join(path)))
+			.collect();
+		project.retain_changed(&mut actions, &paths);
+		if actions.is_empty() && !options.dry_run {
+			if !options.quiet_unchanged {
+				info!("nothing changed since {git_ref}");
+			}
+			return Ok(());
+		}
+	}
+
+	// without git, --since-commit falls back to hashing
+	let hashes = options.incremental || (options.since_commit.is_some() && changed_files.is_none());
+	if hashes || options.select {
+		let state = State::read(&project.root).await?;
+		let mut cache = HashCache::read(&project.root).await?;
+		// sources are hashed as they'd be sent, so changing how they're normalized changes the hash
+		let normalization =
+			serde_json::to_string(&(project.configuration.trailing_newline, options.keep_bom))?;
+
+		let mut changed = Vec::with_capacity(actions.len());
+		let mut unchanged = Vec::new();
+		for action in actions {
+			let fingerprint = match action.text() {
+				Some(text) => {
+					cache
+						.hash(&project.source_of(&action), text, &normalization)
+						.await?
+				}
+				None => fingerprint(&action),
+			};
+			match state.is_synced(&action, &fingerprint) {
+				true => unchanged.push(action.label()),
+				false => changed.push(action),
+			}
+		}
+
+		cache.write(&project.root).await?;
+		if !unchanged.is_empty() && !options.quiet_unchanged {
+			info!("unchanged since the last sync: {}", unchanged.join(", "));
+		}
+		actions = changed;
+		if actions.is_empty() && !options.dry_run {
+			if !options.quiet_unchanged {
+				info!("nothing changed since the last sync");
+			}
+			return Ok(());
+		}
+	}
+
+	if options.select {
+		let labels: Vec<String> = actions.iter().map(EditorUpdate::label).collect();
+		let chosen = prompt::select(
+			"what should be pushed?",
+			&labels,
+			"pass --no-description, --no-whitelist and --no-publicity instead of --select",
+		)?;
+		let (selected, deselected): (Vec<_>, Vec<_>) = actions
+			.into_iter()
+			.enumerate()
+			.partition(|(index, _)| chosen.contains(index));
+		report
+			.skipped
+			.extend(deselected.iter().map(|(_, action)| action.label()));
+		actions = selected.into_iter().map(|(_, action)| action).collect();
+		if actions.is_empty() {
+			info!("nothing selected");
+			return Ok(());
+		}
+	}
+
+	if options.quiet_unchanged || options.dry_run || options.verbose_diff {
+		let remote = cancellable(
+			&options.cancel,
+			client
+				.get_editor(script_id)
+				.instrument(info_span!("validation")),
+		)
+		.await?
+		.script_info;
+		if options.verbose_diff {
+			print_whitelist_diff(&remote.whitelist, &project.whitelist);
+		}
+
+		if options.quiet_unchanged || options.dry_run {
+			let state = State::read(&project.root).await?;
+			report.normalized = actions
+				.iter()
+				.filter(|action| !action.is_applied_to(&remote) && state.is_normalized(action, &remote))
+				.map(EditorUpdate::label)
+				.collect();
+			actions
+				.retain(|action| !action.is_applied_to(&remote) && !state.is_normalized(action, &remote));
+		}
+
+		if options.dry_run {
+			report.record(&actions);
+			if actions.is_empty() && !options.quiet_unchanged {
+				info!("nothing to push");
+			}
+			for action in &actions {
+				println!(
+					"would update {} ({})",
+					action.label(),
+					project.source_of(action).display()
+				);
+			}
+			return Ok(());
+		}
+
+		if actions.is_empty() {
+			return Ok(());
+		}
+	}
+
+	if actions
+		.iter()
+		.any(|action| matches!(action, EditorUpdate::Publicity(true)))
+		&& !cancellable(&options.cancel, client.get_editor(script_id))
+			.await?
+			.script_info
+			.is_public
+	{
+		warn!(target: "safety", "this push makes {script_id} PUBLIC; anyone will be able to see and run it");
+		if !options.confirm_publicity
+			&& !prompt::confirm(
+				&format!("make {script_id} public?"),
+				"pass --confirm-publicity to make the script public",
+			)? {
+			return Err(Error::PublicityNotConfirmed(script_id.clone()));
+		}
+	}
+
+	if options.cancel.is_cancelled() {
+		return Err(Error::Cancelled);
+	}
+	report.record(&actions);
+	let network = async {
+		let Some(concurrency) = project.configuration.module_concurrency else {
+			let Some(limits) = &project.configuration.batch else {
+				return finish_despite_interrupt(client.set_editor(script_id, &actions)).await;
+			};
+
+			let batches = limits.split(&actions);
+			for (index, batch) in batches.iter().enumerate() {
+				if index > 0 && options.cancel.is_cancelled() {
+					return Err(Error::Cancelled);
+				}
+				// the batches sent so far stay sent
+				if index > 0 && interrupted() {
+					return Err(Error::PushInterrupted);
+				}
+				finish_despite_interrupt(client.set_editor(script_id, batch)).await?;
+				info!(
+					"sent batch {}/{} ({} updates)",
+					index + 1,
+					batches.len(),
+					batch.len()
+				);
+			}
+			return Ok(());
+		};
+
+		let fields: Vec<EditorUpdate> = actions
+			.iter()
+			.filter(|action| !matches!(action, EditorUpdate::Module { .. }))
+			.cloned()
+			.collect();
+		let modules = actions
+			.iter()
+			.filter_map(|action| match action {
+				EditorUpdate::Module { name, source } => Some((name.to_string(), source.to_string())),
+				_ => None,
+			})
+			.collect();
+
+		if !fields.is_empty() {
+			finish_despite_interrupt(client.set_editor(script_id, &fields)).await?;
+		}
+		if interrupted() {
+			return Err(Error::PushInterrupted);
+		}
+
+		// the module uploads are all in flight together, so they're guarded together
+		let mut failures = Vec::new();
+		finish_despite_interrupt(async {
+			failures = client.set_modules(script_id, modules, concurrency).await;
+			Ok(())
+		})
+		.await?;
+		if failures.is_empty() {
+			return Ok(());
+		}
+		for (name, error) in &failures {
+			warn!(target: "network", "failed uploading module {name}: {error}");
+		}
+		Err(Error::ModuleUploadsFailed(
+			failures.into_iter().map(|(name, _)| name).collect(),
+		))
+	};
+
+	let budget = project.configuration.budget.as_ref();
+	let network = network.instrument(info_span!("network", updates = actions.len()));
+	match budget.and_then(|budget| budget.max_seconds) {
+		Some(max_seconds) => tokio::time::timeout(Duration::from_secs(max_seconds), network)
+			.await
+			.map_err(|_| Error::TimeBudgetExceeded(max_seconds))??,
+		None => network.await?,
+	}
+	info!("pushed {} updates to {script_id}", actions.len());
+
+	// the state describes the linked script
+	if options.target.is_some() {
+		if options.verify {
+			let stored = client.get_editor(script_id).await?.script_info;
+			verify_pushed(&actions, &stored, options.diagnostics)?;
+		}
+		return Ok(());
+	}
+	let mut state = State::read(&project.root).await?;
+	for action in &actions {
+		state.mark_synced(action);
+	}
+	if let Some(message) = &options.message {
+		state.history.push(PushRecord {
+			time: Utc::now(),
+			script_id: script_id.clone(),
+			message: message.clone(),
+			updates: actions.iter().map(EditorUpdate::label).collect(),
+		});
+	}
+	state.write(&project.root).await?;
+
+	if options.record_normalization || options.verify {
+		let stored = client.get_editor(script_id).await?.script_info;
+		if options.record_normalization {
+			for action in &actions {
+				state.record(action, &stored);
+			}
+			state.write(&project.root).await?;
+		}
+		if options.verify {
+			verify_pushed(&actions, &stored, options.diagnostics)?;
+		}
+	}
+
+	Ok(())
+}
+
+/// `push --main-stdin`
+async fn push_stdin_main(
+	options: &PushOptions,
+	client: &Client,
+	report: &mut PushReport,
+) -> Result<(), Error> {
+	let configuration = read_configuration(Path::new(".")).await?;
+	let script_id = &configuration.script_id;
+	report.script_id = script_id.clone();
+	if std::io::stdin().is_terminal() {
+		info!("reading the main source from stdin until end of file");
+	}
+	let mut source = String::new();
+	cancellable(&options.cancel, async {
+		tokio::io::stdin()
+			.read_to_string(&mut source)
+			.await
+			.map_err(Error::from)
+	})
+	.await?;
+	let source = match options.keep_bom {
+		true => source.as_str(),
+		false => source.strip_prefix('\u{feff}').unwrap_or(&source),
+	};
+
+	let update = EditorUpdate::MainSource(source);
+	report.candidates = vec![update.label()];
+	if source.trim().is_empty() && !options.push_empty && !options.allow_empty_main {
+		report.skipped = report.candidates.clone();
+		return options.diagnostics.warn(
+			Category::Fidelity,
+			"stdin is blank, leaving the remote main source alone (pass --push-empty to push it anyway)",
+		);
+	}
+	let actions = [update];
+	report.record(&actions);
+	if options.dry_run {
+		println!(
+			"would update the main source of {script_id} ({} bytes from stdin)",
+			source.len()
+		);
+		return Ok(());
+	}
+
+	if options.cancel.is_cancelled() {
+		return Err(Error::Cancelled);
+	}
+	finish_despite_interrupt(client.set_editor(script_id, &actions))
+		.instrument(info_span!("network", updates = 1))
+		.await?;
+	info!("pushed the main source from stdin to {script_id}");
+	if options.verify {
+		let stored = client.get_editor(script_id).await?.script_info;
+		verify_pushed(&actions, &stored, options.diagnostics)?;
+	}
+	Ok(())
+}
+
+/// Pushes the project in the current directory to another (empty) script, then copies the
+/// project into `project_directory` linked to that script.
+pub async fn fork_project(
+	script_id: String,
+	project_directory: PathBuf,
+	force: bool,
+	format: ConfigFormat,
+	options: &PushOptions,
+	client: &Client,
+) -> Result<(), Error> {
+	if project_directory.exists() {
+		return Err(Error::DirectoryAlreadyExists(project_directory));
+	}
+
+	let mut project = LocalProject::read(Path::new("."), options).await?;
+
+	let target = client.get_editor(&script_id).await?.script_info;
+	if !force && (!target.source.main.trim().is_empty() || !target.source.modules.is_empty()) {
+		return Err(Error::ForkTargetNotEmpty(script_id));
+	}
+
+	// the same checks and filtering as a push, leaving out what the target already holds
+	let options = PushOptions {
+		target: Some(script_id.clone()),
+		quiet_unchanged: true,
+		..options.clone()
+	};
+	push_project(&options, client).await?;
+
+	// list everything up front so a destination inside the project isn't copied into itself
+	let mut files = Vec::new();
+	let mut directories = vec![PathBuf::new()];
+	while let Some(relative) = directories.pop() {
+		let directory = project.root.join(&relative);
+		let mut stream = tokio::fs::read_dir(&directory)
+			.await
+			.with_path(Error::ReadDirectory, &directory)?;
+		while let Some(entry) = stream.next_entry().await? {
+			let name = entry.file_name();
+			if relative.as_os_str().is_empty() && name == STATE_DIRECTORY {
+				continue;
+			}
+			// history and secrets stay with the original
+			let name = name.to_string_lossy();
+			if name == ".git" || name == ".env" || name.starts_with(".env.") {
+				continue;
+			}
+			let path = relative.join(entry.file_name());
+			if entry.file_type().await?.is_dir() {
+				directories.push(path);
+			} else {
+				files.push(path);
+			}
+		}
+	}
+
+	for file in files {
+		let destination = project_directory.join(&file);
+		if let Some(parent) = destination.parent() {
+			tokio::fs::create_dir_all(parent)
+				.await
+				.with_path(Error::CreateDirectory, parent)?;
+		}
+		tokio::fs::copy(project.root.join(&file), &destination)
+			.await
+			.with_path(Error::CreateFile, &destination)?;
+	}
+
+	project.configuration.script_id = script_id.clone();
+	let configuration_name = match is_toml(&configuration_path(&project.root)) {
+		true => "fumosync.toml",
+		false => "fumosync.json",
+	};
+	write_configuration(
+		project_directory.join(configuration_name),
+		&project.configuration,
+		format,
+	)
+	.await?;
+
+	info!(
+		"forked into {script_id}, linked at {}",
+		project_directory.display()
+	);
+	Ok(())
+}
+
+/// Detaches the project in the current directory from its remote script, forgetting any recorded
+/// remote state but leaving every source file untouched.
+pub async fn unlink_project(format: ConfigFormat) -> Result<(), Error> {
+	let root = Path::new(".");
+	let mut configuration = read_configuration(root).await?;
+	let previous = std::mem::replace(
+		&mut configuration.script_id,
+		PLACEHOLDER_SCRIPT_ID.to_owned(),
+	);
+	write_configuration(configuration_path(root), &configuration, format).await?;
+
+	let state_directory = root.join(STATE_DIRECTORY);
+	if tokio::fs::try_exists(&state_directory).await? {
+		tokio::fs::remove_dir_all(&state_directory)
+			.await
+			.with_path(Error::RemoveDirectory, &state_directory)?;
+	}
+
+	warn!(
+		"unlinked from {previous}; set scriptId in {} before pushing again",
+		configuration_path(root).display()
+	);
+	Ok(())
+}
+
+/// Prints the entries a push adds to (`+`) and removes from (`-`) the remote whitelist.
+fn print_whitelist_diff(remote: &[String], local: &[String]) {
+	for entry in local.iter().filter(|entry| !remote.contains(entry)) {
+		println!("+ {entry}");
+	}
+	for entry in remote.iter().filter(|entry| !local.contains(entry)) {
+		println!("- {entry}");
+	}
+}
+
+/// Runs `future` unless `cancel` is triggered first, in which case it's dropped.
+async fn cancellable<T>(
+	cancel: &CancellationToken,
+	future: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+	tokio::select! {
+		result = future => result,
+		_ = cancel.cancelled() => Err(Error::Cancelled),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::client::{ScriptType, Source};
+
+	#[test]
+	fn case_variants_get_distinct_paths() {
+		let mut aliases = BTreeMap::new();
+		alias_case_collisions(
+			&["Net", "net", "NET"],
+			Path::new("pkg"),
+			&mut aliases,
+			CaseCollisions::Suffix,
+			|_| false,
+		)
+		.unwrap();
+
+		assert_eq!(aliases.len(), 2);
+		let mut paths: Vec<String> = aliases
+			.keys()
+			.map(|path| path.to_string_lossy().to_lowercase())
+			.collect();
+		paths.push("pkg/net.luau".to_owned());
+		paths.sort();
+		paths.dedup();
+		assert_eq!(paths.len(), 3);
+		assert!(aliases.values().any(|name| name == "net"));
+		assert!(aliases.values().any(|name| name == "NET"));
+	}
+
+	#[test]
+	fn case_suffixes_skip_existing_aliases() {
+		let mut aliases = BTreeMap::from([(PathBuf::from("pkg/NET_2.luau"), "other".to_owned())]);
+		alias_case_collisions(
+			&["net", "Net"],
+			Path::new("pkg"),
+			&mut aliases,
+			CaseCollisions::Suffix,
+			|_| false,
+		)
+		.unwrap();
+
+		assert_eq!(
+			aliases.get(Path::new("pkg/Net_3.luau")).map(String::as_str),
+			Some("Net")
+		);
+	}
+
+	#[test]
+	fn module_path_finds_modules_in_any_directory() {
+		let root = tempfile::tempdir().unwrap();
+		std::fs::create_dir_all(root.path().join("lib")).unwrap();
+		std::fs::write(root.path().join("lib/util.luau"), "").unwrap();
+		let configuration = Configuration {
+			module_directories: vec![PathBuf::from("pkg"), PathBuf::from("lib")],
+			..Configuration::default()
+		};
+
+		assert_eq!(
+			configuration.module_path(root.path(), "util"),
+			root.path().join("lib/util.luau")
+		);
+		assert_eq!(
+			configuration.module_path(root.path(), "new"),
+			root.path().join("pkg/new.luau")
+		);
+	}
+
+	fn script_info(main: &str, modules: &[(&str, &str)]) -> EditorScriptInfo {
+		EditorScriptInfo {
+			name: "roundtrip".to_owned(),
+			script_type: ScriptType::Regular,
+			description: "A script\n".to_owned(),
+			is_public: false,
+			whitelist: vec!["1".to_owned()],
+			source: Source {
+				main: main.to_owned(),
+				modules: modules
+					.iter()
+					.map(|(name, source)| (name.to_string(), source.to_string()))
+					.collect(),
+			},
+		}
+	}
+
+	#[tokio::test]
+	async fn modules_survive_a_roundtrip() {
+		let scratch = tempfile::tempdir().unwrap();
+		let remote = script_info(
+			"local net = require(\"net\")\n",
+			&[
+				("net", "return {}\n"),
+				("util", "return 1"),
+				("Data", "return \"\u{e9}\"\r\n"),
+			],
+		);
+
+		let divergences =
+			roundtrip_divergences(&scratch.path().join("project"), "id".to_owned(), remote)
+				.await
+				.unwrap();
+		assert_eq!(divergences, Vec::new());
+	}
+
+	#[tokio::test]
+	async fn roundtrip_reports_stripped_byte_order_marks() {
+		let scratch = tempfile::tempdir().unwrap();
+		let directory = scratch.path().join("project");
+		let remote = script_info("", &[("bom", "\u{feff}return {}\n")]);
+
+		let divergences = roundtrip_divergences(&directory, "id".to_owned(), remote)
+			.await
+			.unwrap();
+		assert_eq!(divergences.len(), 1);
+		assert_eq!(divergences[0].0, directory.join("pkg/bom.luau"));
+	}
+
+	#[tokio::test]
+	async fn zero_module_roundtrip_keeps_the_module_directory() {
+		let scratch = tempfile::tempdir().unwrap();
+		let directory = scratch.path().join("project");
+		init(directory.clone(), None, ConfigFormat::Pretty)
+			.await
+			.unwrap();
+		let pull = |options: PullOptions| {
+			let directory = directory.clone();
+			async move {
+				let existing = read_configuration(&directory).await.ok();
+				hydrate(
+					&directory,
+					"id".to_owned(),
+					script_info("", &[]),
+					existing,
+					ConfigFormat::Pretty,
+					&options,
+				)
+				.await
+			}
+		};
+
+		pull(PullOptions::default()).await.unwrap();
+		assert!(directory.join("pkg").is_dir());
+
+		std::fs::write(directory.join("pkg/stale.luau"), "return nil").unwrap();
+		pull(PullOptions {
+			prune: true,
+			prune_empty_dirs: true,
+			..PullOptions::default()
+		})
+		.await
+		.unwrap();
+		assert!(!directory.join("pkg/stale.luau").exists());
+		assert!(directory.join("pkg").is_dir());
+
+		let project = LocalProject::read(&directory, &PushOptions::default())
+			.await
+			.unwrap();
+		assert!(project.modules.is_empty());
+	}
+
+	#[tokio::test]
+	async fn formatted_files_are_synced_as_written() {
+		let scratch = tempfile::tempdir().unwrap();
+		let directory = scratch.path().join("project");
+		init(directory.clone(), None, ConfigFormat::Pretty)
+			.await
+			.unwrap();
+		let configuration = Configuration {
+			formatter: Some(vec!["sed".to_owned(), "-i".to_owned(), "s/1/2/".to_owned()]),
+			..Configuration::default()
+		};
+
+		hydrate(
+			&directory,
+			"id".to_owned(),
+			script_info("", &[("util", "return 1\n")]),
+			Some(configuration),
+			ConfigFormat::Pretty,
+			&PullOptions::default(),
+		)
+		.await
+		.unwrap();
+
+		let written = std::fs::read_to_string(directory.join("pkg/util.luau")).unwrap();
+		assert_eq!(written, "return 2\n");
+		let state = State::read(&directory).await.unwrap();
+		assert_eq!(state.synced.get("modules/util"), Some(&hash(&written)));
+	}
+
+	#[test]
+	fn groups_file_changes_keep_the_whitelist() {
+		let root = PathBuf::from("/project");
+		let project = LocalProject {
+			root: root.clone(),
+			configuration: Configuration::default(),
+			whitelist: vec!["1".to_owned()],
+			description_path: root.join("README.md"),
+			description: String::new(),
+			main_path: root.join("init.server.luau"),
+			main_source: String::new(),
+			modules: Vec::new(),
+		};
+		let mut actions = vec![
+			EditorUpdate::Whitelist(vec!["1"]),
+			EditorUpdate::MainSource(""),
+		];
+
+		let changed = HashSet::from([root.join("fumosync.groups.json")]);
+		project.retain_changed(&mut actions, &changed);
+		assert_eq!(actions.len(), 1);
+		assert!(matches!(actions[0], EditorUpdate::Whitelist(_)));
+	}
+
+	#[tokio::test]
+	async fn untouched_files_with_a_byte_order_mark_are_overwritten() {
+		let scratch = tempfile::tempdir().unwrap();
+		let path = scratch.path().join("util.luau");
+		std::fs::write(&path, "\u{feff}return 1\n").unwrap();
+		let update = EditorUpdate::Module {
+			name: "util",
+			source: "return 1\n",
+		};
+		// what a push of the file recorded as synced
+		let synced = hash("return 1\n");
+
+		let written = write_pulled(
+			&path,
+			"return 2\n",
+			Some(&synced),
+			ConflictPolicy::Skip,
+			|local: &mut String| as_pushed(&update, local, None),
+		)
+		.await
+		.unwrap();
+		assert!(written);
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "return 2\n");
+	}
+
+	#[tokio::test]
+	async fn repair_recreates_configured_paths() {
+		let scratch = tempfile::tempdir().unwrap();
+		let directory = scratch.path().to_path_buf();
+		std::fs::write(
+			directory.join("fumosync.json"),
+			r#"{
+	"scriptName": "repaired",
+	"scriptId": "???",
+	"whitelist": [],
+	"isPublic": false,
+	"descriptionFile": "docs/about.md",
+	"moduleDirectories": ["src/modules"],
+	"files": { "main": "src/main.server.luau" }
+}"#,
+		)
+		.unwrap();
+
+		repair(directory.clone(), ConfigFormat::Pretty)
+			.await
+			.unwrap();
+		assert!(directory.join("src/main.server.luau").is_file());
+		assert!(directory.join("docs/about.md").is_file());
+		assert!(directory.join("src/modules").is_dir());
+		assert!(!directory.join("init.server.luau").exists());
+		assert!(!directory.join("README.md").exists());
+	}
+
+	#[tokio::test]
+	async fn pruning_skips_excluded_modules() {
+		let scratch = tempfile::tempdir().unwrap();
+		let directory = scratch.path().join("project");
+		init(directory.clone(), None, ConfigFormat::Pretty)
+			.await
+			.unwrap();
+		std::fs::write(directory.join("pkg/local.luau"), "return nil").unwrap();
+
+		hydrate(
+			&directory,
+			"id".to_owned(),
+			script_info("", &[]),
+			Some(read_configuration(&directory).await.unwrap()),
+			ConfigFormat::Pretty,
+			&PullOptions {
+				prune: true,
+				exclude_modules: true,
+				..PullOptions::default()
+			},
+		)
+		.await
+		.unwrap();
+		assert!(directory.join("pkg/local.luau").is_file());
+	}
 }
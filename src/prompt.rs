@@ -0,0 +1,51 @@
+use crate::error::Error;
+use dialoguer::{Confirm, MultiSelect};
+use std::{io::IsTerminal, sync::OnceLock};
+use tracing::warn;
+
+static ASSUME_YES: OnceLock<()> = OnceLock::new();
+
+/// Answers yes to every later prompt without asking, for `--yes`.
+pub fn assume_yes() {
+	let _ = ASSUME_YES.set(());
+}
+
+/// Fails with `NotInteractive` unless there's a terminal to ask on; `flag` is what to pass
+/// instead of answering.
+fn require_terminal(flag: &str) -> Result<(), Error> {
+	match std::io::stdin().is_terminal() && std::io::stderr().is_terminal() {
+		true => Ok(()),
+		false => Err(Error::NotInteractive(flag.to_owned())),
+	}
+}
+
+/// Asks a yes or no question, defaulting to no.
+/// With `--yes`, answers yes and says so loudly instead.
+pub fn confirm(prompt: &str, flag: &str) -> Result<bool, Error> {
+	if ASSUME_YES.get().is_some() {
+		warn!(target: "safety", "{prompt} yes (--yes)");
+		return Ok(true);
+	}
+	require_terminal(flag)?;
+	Confirm::new()
+		.with_prompt(prompt)
+		.default(false)
+		.interact()
+		.map_err(|dialoguer::Error::IO(io_error)| Error::Io(io_error))
+}
+
+/// Asks which of `items` to go ahead with, all of them selected initially, returning the indices
+/// of those chosen. With `--yes`, all of them are chosen without asking.
+pub fn select(prompt: &str, items: &[String], flag: &str) -> Result<Vec<usize>, Error> {
+	if ASSUME_YES.get().is_some() {
+		warn!(target: "safety", "{prompt} all of them (--yes): {}", items.join(", "));
+		return Ok((0..items.len()).collect());
+	}
+	require_terminal(flag)?;
+	MultiSelect::new()
+		.with_prompt(prompt)
+		.items(items)
+		.defaults(&vec![true; items.len()])
+		.interact()
+		.map_err(|dialoguer::Error::IO(io_error)| Error::Io(io_error))
+}
@@ -0,0 +1,264 @@
+use crate::{
+	client::{EditorScriptInfo, EditorUpdate},
+	error::{Error, PathContext},
+	project::{read_file, write_file},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	time::UNIX_EPOCH,
+};
+
+/// Directory (relative to the project root) holding what fumosync remembers about the remote.
+pub const STATE_DIRECTORY: &str = ".fumosync";
+
+/// .fumosync/state.json
+#[derive(Deserialize, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct State {
+	/// Field key -> fingerprint of the value last pushed to or pulled from that field.
+	#[serde(default)]
+	pub synced: BTreeMap<String, String>,
+	/// Module names in the order the server listed them at the last pull.
+	#[serde(default)]
+	pub module_order: Vec<String>,
+	/// Field key -> how the server rewrote the last value pushed to that field.
+	#[serde(default)]
+	pub normalizations: BTreeMap<String, Normalization>,
+	/// Pushes made with `--message`, oldest first.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub history: Vec<PushRecord>,
+}
+
+/// A push and the note it was made with.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushRecord {
+	pub time: DateTime<Utc>,
+	pub script_id: String,
+	pub message: String,
+	/// Labels of the fields and modules that were pushed.
+	pub updates: Vec<String>,
+}
+
+/// A value the server stored differently from how it was sent, identified by content hashes.
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+pub struct Normalization {
+	pub sent: String,
+	pub stored: String,
+}
+
+pub fn hash(contents: &str) -> String {
+	format!("{:x}", Sha256::digest(contents.as_bytes()))
+}
+
+/// Key identifying the field an update writes to.
+pub fn field_key(update: &EditorUpdate) -> String {
+	match update {
+		EditorUpdate::Description(_) => "description".to_owned(),
+		EditorUpdate::MainSource(_) => "main".to_owned(),
+		EditorUpdate::Module { name, .. } => format!("modules/{name}"),
+		EditorUpdate::Name(_) => "name".to_owned(),
+		EditorUpdate::Whitelist(_) => "whitelist".to_owned(),
+		EditorUpdate::Publicity(_) => "publicity".to_owned(),
+	}
+}
+
+/// Hash of the value an update carries.
+pub fn fingerprint(update: &EditorUpdate) -> String {
+	match update {
+		EditorUpdate::Whitelist(whitelist) => hash(&whitelist.join("\n")),
+		EditorUpdate::Publicity(public) => hash(&public.to_string()),
+		EditorUpdate::Name(name) => hash(name),
+		_ => hash(update.text().expect("sources to have text")),
+	}
+}
+
+/// The updates that would make a project hold exactly what the remote script `info` holds.
+pub fn remote_updates(info: &EditorScriptInfo) -> Vec<EditorUpdate<'_>> {
+	let whitelist = info.whitelist.iter().map(String::as_str).collect();
+	let mut updates = Vec::from([
+		EditorUpdate::Name(&info.name),
+		EditorUpdate::Whitelist(whitelist),
+		EditorUpdate::Publicity(info.is_public),
+		EditorUpdate::Description(&info.description),
+		EditorUpdate::MainSource(&info.source.main),
+	]);
+	for (name, source) in &info.source.modules {
+		updates.push(EditorUpdate::Module { name, source });
+	}
+	updates
+}
+
+/// The text an update sends and what the remote currently holds for the same field.
+fn texts<'a>(update: &'a EditorUpdate, remote: &'a EditorScriptInfo) -> Option<(&'a str, &'a str)> {
+	match update {
+		EditorUpdate::Description(value) => Some((value, &remote.description)),
+		EditorUpdate::MainSource(value) => Some((value, &remote.source.main)),
+		EditorUpdate::Module { name, source } => remote
+			.source
+			.modules
+			.get(*name)
+			.map(|stored| (*source, stored.as_str())),
+		EditorUpdate::Name(_) | EditorUpdate::Whitelist(_) | EditorUpdate::Publicity(_) => None,
+	}
+}
+
+impl State {
+	pub async fn read(root: &Path) -> Result<Self, Error> {
+		let path = root.join(STATE_DIRECTORY).join("state.json");
+		if !tokio::fs::try_exists(&path).await? {
+			return Ok(Self::default());
+		}
+		Ok(serde_json::from_str(&read_file(path).await?)?)
+	}
+
+	pub async fn write(&self, root: &Path) -> Result<(), Error> {
+		let directory = root.join(STATE_DIRECTORY);
+		tokio::fs::create_dir_all(&directory)
+			.await
+			.with_path(Error::CreateDirectory, &directory)?;
+		write_file(
+			directory.join("state.json"),
+			&serde_json::to_string_pretty(self)?,
+		)
+		.await
+	}
+
+	/// Whether a value with this fingerprint is what was last synced to the update's field.
+	pub fn is_synced(&self, update: &EditorUpdate, fingerprint: &str) -> bool {
+		self
+			.synced
+			.get(&field_key(update))
+			.is_some_and(|synced| synced == fingerprint)
+	}
+
+	/// Remembers an update as the field's last synced value.
+	pub fn mark_synced(&mut self, update: &EditorUpdate) {
+		self.synced.insert(field_key(update), fingerprint(update));
+	}
+
+	/// Replaces everything remembered as synced with the contents of a freshly pulled script.
+	pub fn mark_pulled(&mut self, info: &EditorScriptInfo) {
+		self.synced.clear();
+		self.module_order = info.source.modules.keys().cloned().collect();
+
+		for update in &remote_updates(info) {
+			self.mark_synced(update);
+		}
+	}
+
+	/// Whether the remote holds exactly what the server turned this update's value into last time.
+	pub fn is_normalized(&self, update: &EditorUpdate, remote: &EditorScriptInfo) -> bool {
+		let Some((sent, stored)) = texts(update, remote) else {
+			return false;
+		};
+
+		self.normalizations.get(&field_key(update))
+			== Some(&Normalization {
+				sent: hash(sent),
+				stored: hash(stored),
+			})
+	}
+
+	/// Records how the server stored an update that was just pushed, forgetting fields it kept
+	/// verbatim.
+	pub fn record(&mut self, update: &EditorUpdate, remote: &EditorScriptInfo) {
+		let Some((sent, stored)) = texts(update, remote) else {
+			return;
+		};
+
+		if sent == stored {
+			self.normalizations.remove(&field_key(update));
+		} else {
+			self.normalizations.insert(
+				field_key(update),
+				Normalization {
+					sent: hash(sent),
+					stored: hash(stored),
+				},
+			);
+		}
+	}
+}
+
+/// .fumosync/cache.json; content hashes of local files, reused while a file's size and mtime
+/// stay the same.
+#[derive(Deserialize, Serialize, Default)]
+pub struct HashCache {
+	#[serde(default)]
+	entries: BTreeMap<PathBuf, CachedHash>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedHash {
+	size: u64,
+	modified_secs: u64,
+	modified_nanos: u32,
+	/// How the contents were normalized before hashing; see `HashCache::hash`.
+	#[serde(default)]
+	normalization: String,
+	hash: String,
+}
+
+impl HashCache {
+	pub async fn read(root: &Path) -> Result<Self, Error> {
+		let path = root.join(STATE_DIRECTORY).join("cache.json");
+		if !tokio::fs::try_exists(&path).await? {
+			return Ok(Self::default());
+		}
+		Ok(serde_json::from_str(&read_file(path).await?)?)
+	}
+
+	pub async fn write(&self, root: &Path) -> Result<(), Error> {
+		let directory = root.join(STATE_DIRECTORY);
+		tokio::fs::create_dir_all(&directory)
+			.await
+			.with_path(Error::CreateDirectory, &directory)?;
+		write_file(directory.join("cache.json"), &serde_json::to_string(self)?).await
+	}
+
+	/// Hash of `contents`, which were read from `path` and then normalized as `normalization`
+	/// describes; only hashes again when the file's size or mtime, or the normalization, no longer
+	/// match the cached entry.
+	pub async fn hash(
+		&mut self,
+		path: &Path,
+		contents: &str,
+		normalization: &str,
+	) -> Result<String, Error> {
+		// nothing to cache for contents that didn't come from a file, like an allowed empty main
+		let Ok(metadata) = tokio::fs::metadata(path).await else {
+			return Ok(hash(contents));
+		};
+		let modified = metadata
+			.modified()?
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default();
+
+		if let Some(entry) = self.entries.get(path) {
+			if entry.size == metadata.len()
+				&& entry.modified_secs == modified.as_secs()
+				&& entry.modified_nanos == modified.subsec_nanos()
+				&& entry.normalization == normalization
+			{
+				return Ok(entry.hash.clone());
+			}
+		}
+
+		let entry = CachedHash {
+			size: metadata.len(),
+			modified_secs: modified.as_secs(),
+			modified_nanos: modified.subsec_nanos(),
+			normalization: normalization.to_owned(),
+			hash: hash(contents),
+		};
+		let hash = entry.hash.clone();
+		self.entries.insert(path.to_path_buf(), entry);
+		Ok(hash)
+	}
+}
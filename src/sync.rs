@@ -0,0 +1,185 @@
+use crate::{
+	client::{Client, EditorScriptInfo, EditorUpdate},
+	diagnostics::Diagnostics,
+	diff::diff_project,
+	error::Error,
+	project::{pull_project, push_project, ConfigFormat, LocalProject, PullOptions, PushOptions},
+	state::{field_key, fingerprint, remote_updates, State},
+};
+use chrono::{DateTime, Local};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Which side of a sync wins when both changed.
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Side {
+	Local,
+	Remote,
+}
+
+/// A field or module, and which sides changed it since the last push or pull.
+struct Field<'a> {
+	/// The local update for this field, or the remote one when it only exists remotely.
+	update: &'a EditorUpdate<'a>,
+	/// Whether the project has this field at all; modules can exist only remotely.
+	is_local: bool,
+	local_changed: bool,
+	remote_changed: bool,
+}
+
+/// Compares every field of the project with the remote script against what was recorded at the
+/// last push or pull. Fields holding the same value on both sides (possibly after the server
+/// normalized what was pushed) count as unchanged.
+fn compare<'a>(
+	local_updates: &'a [EditorUpdate<'a>],
+	remote_updates: &'a [EditorUpdate<'a>],
+	remote: &EditorScriptInfo,
+	state: &State,
+) -> Vec<Field<'a>> {
+	let mut fields = Vec::new();
+	for update in local_updates {
+		let same = update.is_applied_to(remote) || state.is_normalized(update, remote);
+		let remote_changed = remote_updates
+			.iter()
+			.find(|remote_update| field_key(remote_update) == field_key(update))
+			.is_some_and(|remote_update| !state.is_synced(remote_update, &fingerprint(remote_update)));
+		fields.push(Field {
+			update,
+			is_local: true,
+			local_changed: !same && !state.is_synced(update, &fingerprint(update)),
+			remote_changed: !same && remote_changed,
+		});
+	}
+	for remote_update in remote_updates {
+		let key = field_key(remote_update);
+		if !local_updates.iter().any(|update| field_key(update) == key) {
+			fields.push(Field {
+				update: remote_update,
+				is_local: false,
+				local_changed: false,
+				remote_changed: !state.is_synced(remote_update, &fingerprint(remote_update)),
+			});
+		}
+	}
+	fields
+}
+
+/// Pushes the project in the current directory when only it changed since the last push or pull,
+/// pulls when only the remote script did, and otherwise shows the diff and refuses unless `prefer`
+/// picks a side. Modules deleted locally don't count as a change, since a push can't delete them.
+pub async fn sync(
+	prefer: Option<Side>,
+	format: ConfigFormat,
+	diagnostics: Diagnostics,
+	client: &Client,
+) -> Result<(), Error> {
+	let root = Path::new(".");
+	let options = PushOptions {
+		diagnostics,
+		..PushOptions::default()
+	};
+	let project = LocalProject::read(root, &options).await?;
+	let state = State::read(root).await?;
+	let script_id = project.configuration.script_id.clone();
+	let remote = client.get_editor(&script_id).await?.script_info;
+
+	let local_updates = project.updates();
+	let remote_updates = remote_updates(&remote);
+	let fields = compare(&local_updates, &remote_updates, &remote, &state);
+	let local_changed: Vec<String> = fields
+		.iter()
+		.filter(|field| field.local_changed)
+		.map(|field| field.update.label())
+		.collect();
+	let remote_changed: Vec<String> = fields
+		.iter()
+		.filter(|field| field.remote_changed)
+		.map(|field| field.update.label())
+		.collect();
+
+	let side = match (local_changed.is_empty(), remote_changed.is_empty()) {
+		(true, true) => {
+			info!("already in sync with {script_id}");
+			return Ok(());
+		}
+		(false, true) => Side::Local,
+		(true, false) => Side::Remote,
+		(false, false) => match prefer {
+			Some(side) => side,
+			None => {
+				diff_project(false, None, client).await?;
+				return Err(Error::SyncConflict(local_changed, remote_changed));
+			}
+		},
+	};
+
+	match side {
+		Side::Local => {
+			info!("pushing local changes: {}", local_changed.join(", "));
+			push_project(&options, client).await?;
+		}
+		Side::Remote => {
+			info!("pulling remote changes: {}", remote_changed.join(", "));
+			pull_project(
+				script_id,
+				PathBuf::from("."),
+				format,
+				&PullOptions::default(),
+				client,
+			)
+			.await?;
+		}
+	}
+	Ok(())
+}
+
+/// Prints every source of the project in the current directory with when its file was last
+/// modified and which sides changed it since the last push or pull. fumosclub doesn't report when
+/// a script was edited, so there's no remote time; a change made in the web editor shows as
+/// "changed remotely".
+pub async fn status(client: &Client) -> Result<(), Error> {
+	let root = Path::new(".");
+	let project = LocalProject::read(root, &PushOptions::default()).await?;
+	let state = State::read(root).await?;
+	let remote = client
+		.get_editor(&project.configuration.script_id)
+		.await?
+		.script_info;
+
+	let local_updates = project.updates();
+	let remote_updates = remote_updates(&remote);
+	let fields = compare(&local_updates, &remote_updates, &remote, &state);
+
+	let mut rows = Vec::new();
+	for field in fields.iter().filter(|field| field.update.text().is_some()) {
+		let modified = match field.is_local {
+			true => match tokio::fs::metadata(project.source_of(field.update)).await {
+				Ok(metadata) => DateTime::<Local>::from(metadata.modified()?)
+					.format("%Y-%m-%d %H:%M:%S")
+					.to_string(),
+				Err(_) => "-".to_owned(),
+			},
+			false => "-".to_owned(),
+		};
+		let change = match (field.is_local, field.local_changed, field.remote_changed) {
+			(false, _, true) => "only on the remote",
+			(false, _, false) => "deleted locally",
+			(true, false, false) => "in sync",
+			(true, true, false) => "changed locally",
+			(true, false, true) => "changed remotely",
+			(true, true, true) => "changed on both sides",
+		};
+		rows.push((field.update.label(), modified, change));
+	}
+
+	let label_width = rows
+		.iter()
+		.map(|(label, ..)| label.len())
+		.max()
+		.unwrap_or(0);
+	println!("{:label_width$}  {:19}  status", "", "local modified");
+	for (label, modified, change) in rows {
+		println!("{label:label_width$}  {modified:19}  {change}");
+	}
+	Ok(())
+}
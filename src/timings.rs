@@ -0,0 +1,81 @@
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+use tracing::{span, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+/// Records how long every span (the config_read, file_reads, validation and network phases of a
+/// push or pull) took, for `--timings`.
+#[derive(Clone, Default)]
+pub struct Timings {
+	phases: Arc<Mutex<Vec<(&'static str, Duration)>>>,
+}
+
+struct Started(Instant);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Timings {
+	fn on_new_span(
+		&self,
+		_attributes: &span::Attributes<'_>,
+		id: &span::Id,
+		context: Context<'_, S>,
+	) {
+		if let Some(span) = context.span(id) {
+			span.extensions_mut().insert(Started(Instant::now()));
+		}
+	}
+
+	fn on_close(&self, id: span::Id, context: Context<'_, S>) {
+		let Some(span) = context.span(&id) else {
+			return;
+		};
+		let elapsed = span
+			.extensions()
+			.get::<Started>()
+			.map(|Started(started)| started.elapsed());
+		if let Some(elapsed) = elapsed {
+			self
+				.phases
+				.lock()
+				.expect("timings not to be poisoned")
+				.push((span.name(), elapsed));
+		}
+	}
+}
+
+impl Timings {
+	/// Prints the total time spent in each phase, in the order the phases first finished.
+	pub fn print(&self) {
+		let mut totals: Vec<(&str, Duration, usize)> = Vec::new();
+		for (name, elapsed) in self
+			.phases
+			.lock()
+			.expect("timings not to be poisoned")
+			.iter()
+		{
+			match totals
+				.iter_mut()
+				.find(|(total_name, ..)| total_name == name)
+			{
+				Some((_, total, count)) => {
+					*total += *elapsed;
+					*count += 1;
+				}
+				None => totals.push((name, *elapsed, 1)),
+			}
+		}
+
+		let width = totals
+			.iter()
+			.map(|(name, ..)| name.len())
+			.max()
+			.unwrap_or(0);
+		for (name, total, count) in totals {
+			match count {
+				1 => println!("{name:width$}  {total:.2?}"),
+				count => println!("{name:width$}  {total:.2?} ({count} times)"),
+			}
+		}
+	}
+}
@@ -0,0 +1,79 @@
+use crate::{
+	client::Client,
+	error::Error,
+	project::{pull_project, read_configuration, read_file, ConfigFormat, PullOptions},
+};
+use serde::Deserialize;
+use std::{path::PathBuf, sync::Arc};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+/// fumosync.workspace.json; a directory grouping several projects.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Workspace {
+	/// Project directories, relative to the workspace file.
+	pub members: Vec<PathBuf>,
+}
+
+impl Workspace {
+	/// Reads the workspace in the current directory.
+	pub async fn read() -> Result<Self, Error> {
+		Ok(serde_json::from_str(
+			&read_file("fumosync.workspace.json").await?,
+		)?)
+	}
+}
+
+/// Refreshes every member of the workspace in the current directory from its linked script, at
+/// most `jobs` at a time. One member failing doesn't stop the others; every result is printed at
+/// the end.
+pub async fn pull_all(
+	jobs: usize,
+	format: ConfigFormat,
+	options: &PullOptions,
+	client: &Client,
+) -> Result<(), Error> {
+	let workspace = Workspace::read().await?;
+	let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+	let mut pulls = JoinSet::new();
+
+	for (index, member) in workspace.members.into_iter().enumerate() {
+		let client = client.clone();
+		let options = options.clone();
+		let semaphore = semaphore.clone();
+		pulls.spawn(async move {
+			let _permit = semaphore
+				.acquire_owned()
+				.await
+				.expect("semaphore to stay open");
+			let result = async {
+				let script_id = read_configuration(&member).await?.script_id;
+				pull_project(script_id, member.clone(), format, &options, &client).await
+			}
+			.await;
+			(index, member, result)
+		});
+	}
+
+	let mut results = Vec::new();
+	while let Some(pull) = pulls.join_next().await {
+		results.push(pull.expect("pull task panicked"));
+	}
+	results.sort_by_key(|(index, _, _)| *index);
+
+	let mut failures = 0;
+	for (_, member, result) in &results {
+		match result {
+			Ok(()) => println!("✓ {}", member.display()),
+			Err(e) => {
+				failures += 1;
+				println!("✗ {}: {e}", member.display());
+			}
+		}
+	}
+
+	match failures {
+		0 => Ok(()),
+		failures => Err(Error::PullsFailed(failures, results.len())),
+	}
+}